@@ -0,0 +1,323 @@
+use crate::core::window::components::{Window, WindowMode, WindowResizeConstraints};
+use crate::core::window::error::WindowError;
+use crate::core::window::resources::Monitor;
+use bevy_ecs::prelude::Entity;
+use bevy_math::{IVec2, UVec2};
+use winit::window::{CursorGrabMode, Theme, WindowButtons, WindowId};
+
+/// The platform layer that owns the native windows behind `Window` components
+///
+/// `WinitWindows` is the default backend.
+/// Systems that only create, destroy, move, resize or measure windows, or change simple state
+/// like their visibility or mode, are generic over this trait, so they can also run against a
+/// backend that doesn't open any real windows.
+pub trait WindowBackend: 'static {
+    /// What the backend needs to create windows
+    /// For winit this is the event loop window target
+    type Target;
+
+    /// Creates the native window of an entity based on its `Window` component
+    /// Any state only known once the window exists (like its current theme) is written back
+    fn create_window(
+        &mut self,
+        target: &Self::Target,
+        entity: Entity,
+        window: &mut Window,
+    ) -> Result<(), WindowError>;
+
+    /// Destroys the native window of an entity
+    /// Returns `false` if the entity didn't have a native window
+    fn destroy_window(&mut self, entity: Entity) -> bool;
+
+    /// Whether the entity currently has a native window
+    fn has_window(&self, entity: Entity) -> bool;
+
+    /// The entity of a native window, e.g. to find the window an event of the platform is for
+    /// `None` once the window is destroyed
+    fn entity_for_window_id(&self, window_id: WindowId) -> Option<Entity>;
+
+    /// Asks the platform to resize the client area of the window, in physical pixels
+    /// Returns `false` if the entity has no native window
+    fn request_inner_size(&mut self, entity: Entity, size: UVec2) -> bool;
+
+    /// Applies the preferred theme of the window, `None` follows the theme of the OS
+    /// Returns the theme the window has now, `None` if it is unknown or there is no native window
+    fn set_theme(&mut self, entity: Entity, theme: Option<Theme>) -> Option<Theme>;
+
+    /// Switches the window to a mode, picking the monitor it selects
+    /// Returns the scale factor of the window afterwards, `None` if there is no native window
+    fn set_mode(&mut self, entity: Entity, mode: WindowMode) -> Option<f64>;
+
+    /// Shows or hides the window
+    fn set_visible(&mut self, entity: Entity, visible: bool);
+
+    /// Minimizes or restores the window
+    fn set_minimized(&mut self, entity: Entity, minimized: bool);
+
+    /// Grabs or releases the cursor
+    /// Returns whether the grab is in effect, which is never the case for `CursorGrabMode::None`
+    /// A failed grab is tried again in the next frames, see `retries_cursor_grab`
+    fn set_cursor_grab(&mut self, entity: Entity, mode: CursorGrabMode) -> bool;
+
+    /// Whether a failed cursor grab of the window is tried again next frame
+    fn retries_cursor_grab(&self, entity: Entity) -> bool;
+
+    /// Forgets the failed cursor grabs of a window, e.g. because it wants another mode now
+    fn reset_cursor_grab_attempts(&mut self, entity: Entity);
+
+    /// Limits the size the user can resize the window to
+    fn set_resize_constraints(&mut self, entity: Entity, constraints: WindowResizeConstraints);
+
+    /// Maximizes or restores the window
+    fn set_maximized(&mut self, entity: Entity, maximized: bool);
+
+    /// Enables or disables the minimize, maximize and close buttons of the title bar
+    /// Does nothing on platforms without them
+    fn set_enabled_buttons(&mut self, entity: Entity, buttons: WindowButtons);
+
+    /// Whether the window is maximized, which the user can change from the title bar
+    /// `None` if the entity has no native window
+    fn is_maximized(&self, entity: Entity) -> Option<bool>;
+
+    /// The mode the window is actually in, which the user can change with a shortcut of the OS
+    /// `current` is returned if the window is in that kind of mode, so the monitor it selects is
+    /// kept. `None` if the entity has no native window
+    fn current_mode(&self, entity: Entity, current: WindowMode) -> Option<WindowMode>;
+
+    /// Lets the user resize the window or not
+    /// Returns whether the window is maximized afterwards, `None` if there is no native window
+    fn set_resizable(&mut self, entity: Entity, resizable: bool) -> Option<bool>;
+
+    /// The position of the top left corner of the window, including decorations, on the desktop
+    /// in physical pixels
+    /// `None` if the entity has no native window or the platform can't tell (like Wayland)
+    fn outer_position(&self, entity: Entity) -> Option<IVec2>;
+
+    /// Moves the top left corner of the window, including decorations, in physical pixels
+    /// Fails without moving the window if the position is not on any monitor
+    fn set_outer_position(&mut self, entity: Entity, position: IVec2) -> Result<(), WindowError>;
+
+    /// The monitor the window is on, or the primary monitor if the platform can't tell
+    /// `None` if the entity has no native window or no monitor is known
+    fn current_monitor(&self, entity: Entity) -> Option<Monitor>;
+
+    /// The size of the client area of the window in physical pixels
+    /// `None` if the entity has no native window
+    fn inner_size(&self, entity: Entity) -> Option<UVec2>;
+
+    /// The size of the entire window, including decorations, in physical pixels
+    /// `None` if the entity has no native window
+    fn outer_size(&self, entity: Entity) -> Option<UVec2>;
+}
+
+/// A backend that keeps track of windows without opening any, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MockBackend {
+    /// The `Window` component of each open window, as it was when the window was created
+    pub windows: std::collections::HashMap<Entity, Window>,
+    /// Every entity a window was created for, in order
+    pub created: Vec<Entity>,
+    /// Every entity whose window was destroyed, in order
+    pub destroyed: Vec<Entity>,
+    /// Every size passed to `request_inner_size`, in order
+    pub resize_requests: Vec<(Entity, UVec2)>,
+    /// How many of the next creations fail
+    pub failing_creations: u32,
+    /// The theme of the OS, which windows without a preferred theme have
+    pub system_theme: Option<Theme>,
+    /// The scale factor of the monitor windows open on or switch their mode on, `None` keeps the
+    /// one they have
+    pub scale_factor: Option<f64>,
+    /// Whether making a maximized window non-resizable restores it, like some platforms do
+    pub restores_locked_windows: bool,
+    /// The monitors windows can be on, a window is on the primary one until it is moved
+    pub monitors: crate::core::window::resources::Monitors,
+    /// How many of the next cursor grabs fail
+    pub failing_cursor_grabs: u32,
+    /// The failed grabs that are tried again, like `WinitWindows` keeps them
+    pub cursor_grab_attempts: crate::core::window::resources::CursorGrabAttempts,
+}
+
+#[cfg(test)]
+impl MockBackend {
+    /// The id the native window of an entity has while it is open
+    pub fn window_id(entity: Entity) -> WindowId {
+        WindowId::from(entity.to_bits())
+    }
+}
+
+#[cfg(test)]
+impl WindowBackend for MockBackend {
+    type Target = ();
+
+    fn create_window(
+        &mut self,
+        _target: &(),
+        entity: Entity,
+        window: &mut Window,
+    ) -> Result<(), WindowError> {
+        if self.failing_creations > 0 {
+            self.failing_creations -= 1;
+            return Err(WindowError::Unsupported("creating windows"));
+        }
+        window.theme = window.preferred_theme.or(self.system_theme);
+        if let Some(scale_factor) = self.scale_factor {
+            window.resolution.scale_factor = scale_factor;
+        }
+        self.windows.insert(entity, window.clone());
+        self.created.push(entity);
+        Ok(())
+    }
+
+    fn destroy_window(&mut self, entity: Entity) -> bool {
+        if self.windows.remove(&entity).is_none() {
+            return false;
+        }
+        self.destroyed.push(entity);
+        true
+    }
+
+    fn has_window(&self, entity: Entity) -> bool {
+        self.windows.contains_key(&entity)
+    }
+
+    fn entity_for_window_id(&self, window_id: WindowId) -> Option<Entity> {
+        self.windows
+            .keys()
+            .copied()
+            .find(|&entity| MockBackend::window_id(entity) == window_id)
+    }
+
+    fn request_inner_size(&mut self, entity: Entity, size: UVec2) -> bool {
+        if !self.has_window(entity) {
+            return false;
+        }
+        self.resize_requests.push((entity, size));
+        true
+    }
+
+    fn set_theme(&mut self, entity: Entity, theme: Option<Theme>) -> Option<Theme> {
+        let window = self.windows.get_mut(&entity)?;
+        window.preferred_theme = theme;
+        theme.or(self.system_theme)
+    }
+
+    fn set_mode(&mut self, entity: Entity, mode: WindowMode) -> Option<f64> {
+        let window = self.windows.get_mut(&entity)?;
+        window.mode = mode;
+        if let Some(scale_factor) = self.scale_factor {
+            window.resolution.scale_factor = scale_factor;
+        }
+        Some(window.resolution.scale_factor)
+    }
+
+    fn set_visible(&mut self, entity: Entity, visible: bool) {
+        if let Some(window) = self.windows.get_mut(&entity) {
+            window.visible = visible;
+        }
+    }
+
+    fn set_minimized(&mut self, entity: Entity, minimized: bool) {
+        if let Some(window) = self.windows.get_mut(&entity) {
+            window.minimized = minimized;
+        }
+    }
+
+    fn set_cursor_grab(&mut self, entity: Entity, mode: CursorGrabMode) -> bool {
+        let Some(window) = self.windows.get_mut(&entity) else {
+            return false;
+        };
+        let failing = &mut self.failing_cursor_grabs;
+        self.cursor_grab_attempts.grab(entity, mode, |mode| {
+            if *failing > 0 {
+                *failing -= 1;
+                return Err(winit::error::ExternalError::Ignored);
+            }
+            window.cursor_grab_mode = mode;
+            Ok(())
+        })
+    }
+
+    fn retries_cursor_grab(&self, entity: Entity) -> bool {
+        self.cursor_grab_attempts.retries(entity)
+    }
+
+    fn reset_cursor_grab_attempts(&mut self, entity: Entity) {
+        self.cursor_grab_attempts.reset(entity);
+    }
+
+    fn set_resize_constraints(&mut self, entity: Entity, constraints: WindowResizeConstraints) {
+        if let Some(window) = self.windows.get_mut(&entity) {
+            window.resize_constraints = constraints;
+        }
+    }
+
+    fn set_maximized(&mut self, entity: Entity, maximized: bool) {
+        if let Some(window) = self.windows.get_mut(&entity) {
+            window.maximized = maximized;
+        }
+    }
+
+    fn set_enabled_buttons(&mut self, entity: Entity, buttons: WindowButtons) {
+        if let Some(window) = self.windows.get_mut(&entity) {
+            window.enabled_buttons = buttons;
+        }
+    }
+
+    fn is_maximized(&self, entity: Entity) -> Option<bool> {
+        self.windows.get(&entity).map(|window| window.maximized)
+    }
+
+    fn current_mode(&self, entity: Entity, current: WindowMode) -> Option<WindowMode> {
+        let mode = self.windows.get(&entity)?.mode;
+        if std::mem::discriminant(&mode) == std::mem::discriminant(&current) {
+            return Some(current);
+        }
+        Some(mode)
+    }
+
+    fn set_resizable(&mut self, entity: Entity, resizable: bool) -> Option<bool> {
+        let window = self.windows.get_mut(&entity)?;
+        window.resizable = resizable;
+        if !resizable && self.restores_locked_windows {
+            window.maximized = false;
+        }
+        Some(window.maximized)
+    }
+
+    fn outer_position(&self, entity: Entity) -> Option<IVec2> {
+        self.windows.get(&entity)?.position
+    }
+
+    fn set_outer_position(&mut self, entity: Entity, position: IVec2) -> Result<(), WindowError> {
+        if self.monitors.monitor_at(position).is_none() {
+            return Err(WindowError::OffScreen(position));
+        }
+        let window = self
+            .windows
+            .get_mut(&entity)
+            .ok_or(WindowError::NoSuchWindow(entity))?;
+        window.position = Some(position);
+        Ok(())
+    }
+
+    fn current_monitor(&self, entity: Entity) -> Option<Monitor> {
+        let window = self.windows.get(&entity)?;
+        window
+            .position
+            .and_then(|position| self.monitors.monitor_at(position))
+            .or(self.monitors.primary())
+            .cloned()
+    }
+
+    fn inner_size(&self, entity: Entity) -> Option<UVec2> {
+        self.windows
+            .get(&entity)
+            .map(|window| window.resolution.physical_size())
+    }
+
+    fn outer_size(&self, entity: Entity) -> Option<UVec2> {
+        self.inner_size(entity)
+    }
+}
@@ -1,17 +1,81 @@
 use crate::core::window::icon;
-use bevy_ecs::prelude::Component;
+use crate::core::window::resources::{Monitor, VideoMode, WinitWindows};
+use crate::core::window::LOG_TARGET;
+use bevy_ecs::prelude::{Component, Entity};
+use bevy_math::{IVec2, UVec2, Vec2};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use winit::dpi::LogicalSize;
+use winit::raw_window_handle::RawWindowHandle;
+use winit::window::{CursorGrabMode, ImePurpose, Theme, WindowButtons, WindowLevel};
 
 /// A marker for the primary window.
 /// There should be only one primary window at any one time.
 #[derive(Component)]
 pub struct PrimaryWindow;
 
+/// A name to address a window by, like `"Inspector"`, e.g. in commands or saved layouts
+/// Look windows up by name with `WindowNames::find_window_by_name`
+#[allow(dead_code)]
+#[derive(Component, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WindowName(pub String);
+
+/// Refers to a window without caring whether it is the primary window or a specific entity
+///
+/// Systems that act on "some window" (screenshots, resizing, ...) take this as a parameter.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WindowRef {
+    /// Whichever entity currently has the `PrimaryWindow` component
+    #[default]
+    Primary,
+    /// A specific window entity
+    Entity(Entity),
+}
+
+#[allow(dead_code)]
+impl WindowRef {
+    /// Resolves the reference to a window entity
+    ///
+    /// `primary_window` is the current primary window entity, which can be obtained with
+    /// `Query<Entity, With<PrimaryWindow>>::get_single().ok()`.
+    pub fn entity(&self, primary_window: Option<Entity>) -> Option<Entity> {
+        match self {
+            WindowRef::Primary => primary_window,
+            WindowRef::Entity(entity) => Some(*entity),
+        }
+    }
+
+    /// Resolves the reference to the live winit window
+    /// `None` if the window doesn't exist or hasn't been created yet
+    pub fn winit_window<'a>(
+        &self,
+        primary_window: Option<Entity>,
+        winit_windows: &'a WinitWindows,
+    ) -> Option<&'a winit::window::Window> {
+        winit_windows.get_window(self.entity(primary_window)?)
+    }
+}
+
+impl From<Entity> for WindowRef {
+    fn from(entity: Entity) -> Self {
+        WindowRef::Entity(entity)
+    }
+}
+
 /// Component description of the window
 #[derive(Component, Clone)]
 pub struct Window {
-    pub width: u32,
-    pub height: u32,
+    /// The size of the window and the scale factor of the OS
+    pub resolution: WindowResolution,
+    /// Control characters like line breaks are removed before the title reaches the platform
     pub title: String,
+    /// Whether the window has a title bar and borders drawn by the platform
+    /// `None` uses `WindowSettings::decorations`, it is filled in when the window is created
+    pub decorations: Option<bool>,
+    /// Whether presenting frames waits for the display, read by the renderer for its surface
+    /// `None` uses `WindowSettings::vsync`, it is filled in when the window is created
+    pub vsync: Option<bool>,
     /// 0 if there is no icon
     pub icon_width: u32,
     /// 0 if there is no icon
@@ -19,17 +83,483 @@ pub struct Window {
     /// A flat vector of RGBA data of the icon
     /// `None` if there is no icon
     pub icon_data: Option<Vec<u8>>,
+    /// More sizes of the icon, so the platform doesn't have to scale a single one
+    ///
+    /// The icon closest to 32x32 is used for the title bar and the largest one for the taskbar
+    /// and the task switcher. Only Windows shows different icons there, other platforms use the
+    /// largest everywhere. Icons with data that doesn't match their size are skipped.
+    pub icons: Vec<WindowIcon>,
+    /// Whether the window is windowed or fullscreen
+    pub mode: WindowMode,
+    /// Whether to minimize the window when it loses focus in exclusive fullscreen (`Fullscreen`)
+    /// Ignored in the other modes
+    pub minimize_on_focus_loss: bool,
+    /// Whether the background of the window is transparent
+    /// Only applied when the window is created
+    pub transparent: bool,
+    /// Where the window sits relative to other windows
+    /// Only applied when the window is created
+    pub window_level: WindowLevel,
+    /// Whether the window receives cursor input
+    /// Set to `false` to let clicks pass through to whatever is beneath the window
+    ///
+    /// For a click-through overlay, combine with `transparent: true` so the windows beneath
+    /// can be seen, and `window_level: WindowLevel::AlwaysOnTop` so the overlay stays above
+    /// the windows it passes clicks to.
+    pub cursor_hittest: bool,
+    /// Whether the cursor is confined to the window or locked in place, like for mouselook
+    ///
+    /// The OS may release the grab (e.g. on alt-tab), it is applied again when the window gets
+    /// focus back. Check `cursor_grab_active` for whether the grab is in effect.
+    pub cursor_grab_mode: CursorGrabMode,
+    /// The theme the window should use
+    /// `None` follows the theme of the operating system, including when it changes
+    pub preferred_theme: Option<Theme>,
+    /// A zoom factor for UI and layout, applied on top of the scale factor of the OS
+    /// For example `2.0` makes everything twice as large, leaving half the logical space
+    pub ui_scale: f32,
+    /// Whether the window accepts input from an input method editor
+    /// Not supported on iOS, Android and the web
+    pub ime_enabled: bool,
+    /// The kind of text being typed, so on-screen keyboards can show the right layout
+    /// and password input can hide the preedit text
+    ///
+    /// Only supported on Wayland, ignored everywhere else.
+    pub ime_purpose: ImePurpose,
+    /// Keeps the display awake and the screensaver away while this window exists
+    /// Useful for cutscenes and video playback
+    ///
+    /// Only supported on Windows for now, other platforms log that it is unsupported once.
+    pub inhibit_screensaver: bool,
+    /// Whether the window takes focus when it opens
+    /// Only applied when the window is created
+    ///
+    /// For a toast or notification window that doesn't interrupt the user, combine it with
+    /// `skip_taskbar` and `window_level`:
+    ///
+    /// ```ignore
+    /// Window {
+    ///     active: false,
+    ///     skip_taskbar: true,
+    ///     window_level: WindowLevel::AlwaysOnTop,
+    ///     ..Default::default()
+    /// }
+    /// ```
+    pub active: bool,
+    /// Hides the window from the taskbar
+    /// Only applied when the window is created, only supported on Windows
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub skip_taskbar: bool,
+    /// Windows with the same identifier are grouped into native tabs on macOS
+    /// Only applied when the window is created, ignored on other platforms
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub tabbing_identifier: Option<String>,
+    /// The workspace (virtual desktop) to open the window on, counting from 0
+    ///
+    /// Only applied when the window is created. This is a hint for window managers that
+    /// support it, but winit can't pass it on any platform yet, so it is currently only logged.
+    /// Tiling window managers on Linux can place windows by their title with their own rules.
+    pub workspace: Option<u32>,
+    /// Whether the window is shown on the desktop
+    pub visible: bool,
+    /// Which of the minimize, maximize and close buttons of the title bar can be used
+    ///
+    /// Supported on Windows, macOS and X11. Wayland and the other platforms ignore it.
+    /// Disabling the close button doesn't stop `Alt+F4` or the taskbar from closing the window.
+    pub enabled_buttons: WindowButtons,
+    /// Whether the user can resize the window by dragging its edges
+    ///
+    /// Some platforms also disable the maximize button of non-resizable windows, so locking a
+    /// maximized window leaves no way to restore it from the title bar. `maximized` is still
+    /// honoured when it is turned off, and it is updated whenever `resizable` changes.
+    pub resizable: bool,
+    /// The smallest and largest size the user can resize the window to
+    pub resize_constraints: WindowResizeConstraints,
+    /// Whether the window is maximized
+    /// Kept up to date when the user maximizes or restores the window
+    pub maximized: bool,
+    /// A native window of another application to embed this window into, like the viewport
+    /// of a plugin inside its host
+    /// Only applied when the window is created
+    ///
+    /// Supported on Windows, macOS and X11, ignored on Wayland, Android, iOS and the web.
+    pub parent_window: Option<ParentWindowHandle>,
+    /// Where to put the cursor once the window opens, in logical pixels from the top left of
+    /// the client area, e.g. its center for immediate mouselook
+    ///
+    /// Only applied when the window is created, or when it is first shown if it starts hidden.
+    /// Wayland doesn't allow moving the cursor.
+    pub initial_cursor_position: Option<Vec2>,
+    /// Whether closing the window has to be confirmed, e.g. for a document with unsaved changes
+    ///
+    /// Close requests of such a window only ask for confirmation: show a dialog when reading
+    /// `WindowCloseRequestedEvent`, then send `ConfirmWindowCloseEvent` to actually close it.
+    /// Other windows close straight away unless the request is denied, see `WindowCloseSet`.
+    pub confirm_close: bool,
+    /// Whether `cursor_grab_mode` is currently in effect, read with `cursor_grab_active`
+    pub(crate) cursor_grab_active: bool,
+    /// Whether the window has keyboard focus, read with `focused`
+    pub(crate) focused: bool,
+    /// Whether the window is minimized or fully hidden, read with `minimized`
+    pub(crate) minimized: bool,
+    /// The theme the window currently has, read with `theme`
+    pub(crate) theme: Option<Theme>,
+    /// The outer position of the window on the desktop, read with `position`
+    pub(crate) position: Option<IVec2>,
+    /// Kept up to date every frame by the window module, read with `inner_safe_insets`
+    pub(crate) safe_insets: SafeInsets,
+}
+
+impl Window {
+    /// The parts of the window covered by notches, rounded corners or system bars
+    /// UI should keep anything important inside of these insets
+    ///
+    /// Always zero on desktop platforms.
+    #[allow(dead_code)]
+    pub fn inner_safe_insets(&self) -> SafeInsets {
+        self.safe_insets
+    }
+
+    /// Whether the cursor is currently grabbed as requested by `cursor_grab_mode`
+    /// Always `false` while the mode is `CursorGrabMode::None`
+    #[allow(dead_code)]
+    pub fn cursor_grab_active(&self) -> bool {
+        self.cursor_grab_active
+    }
+
+    /// Whether the window currently has keyboard focus
+    #[allow(dead_code)]
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Whether the window is minimized, or occluded on platforms that can't tell
+    /// A renderer should skip presenting frames to windows while this is `true`
+    #[allow(dead_code)]
+    pub fn minimized(&self) -> bool {
+        self.minimized
+    }
+
+    /// The theme the window currently has
+    /// `None` if the platform doesn't report the theme or the window hasn't been created yet
+    #[allow(dead_code)]
+    pub fn theme(&self) -> Option<Theme> {
+        self.theme
+    }
+
+    /// The current scale factor between logical and physical pixels, kept in sync every frame
+    /// Follows `WindowResolution::scale_factor_override` when it is set
+    #[allow(dead_code)]
+    pub fn scale_factor(&self) -> f64 {
+        self.resolution.scale_factor()
+    }
+
+    /// The scale factor UI and layout should use, combining `scale_factor` and `ui_scale`
+    #[allow(dead_code)]
+    pub fn effective_scale_factor(&self) -> f64 {
+        self.resolution.scale_factor() * self.ui_scale as f64
+    }
+
+    /// The logical size of the window as seen by UI and layout, which shrinks as `ui_scale` grows
+    #[allow(dead_code)]
+    pub fn ui_logical_size(&self) -> Vec2 {
+        Vec2::new(self.resolution.width as f32, self.resolution.height as f32) / self.ui_scale
+    }
+
+    /// The position of the top left corner of the window, including decorations,
+    /// on the desktop in physical pixels
+    /// Matches `WinitWindows::outer_position`, `None` if the platform can't tell
+    #[allow(dead_code)]
+    pub fn position(&self) -> Option<IVec2> {
+        self.position
+    }
 }
 
 impl Default for Window {
     fn default() -> Self {
         Window {
-            width: 800,
-            height: 600,
+            resolution: WindowResolution::new(800, 600),
             title: "Ruxel".to_string(),
+            decorations: None,
+            vsync: None,
+            icons: Vec::new(),
             icon_width: icon::IMAGE_WIDTH as u32,
             icon_height: icon::IMAGE_HEIGHT as u32,
             icon_data: Some(icon::IMAGE_DATA.to_vec()),
+            mode: WindowMode::Windowed,
+            minimize_on_focus_loss: true,
+            transparent: false,
+            window_level: WindowLevel::Normal,
+            cursor_hittest: true,
+            cursor_grab_mode: CursorGrabMode::None,
+            preferred_theme: None,
+            ui_scale: 1.0,
+            ime_enabled: false,
+            ime_purpose: ImePurpose::Normal,
+            inhibit_screensaver: false,
+            active: true,
+            skip_taskbar: false,
+            tabbing_identifier: None,
+            workspace: None,
+            visible: true,
+            enabled_buttons: WindowButtons::all(),
+            resizable: true,
+            resize_constraints: WindowResizeConstraints::default(),
+            maximized: false,
+            parent_window: None,
+            initial_cursor_position: None,
+            confirm_close: false,
+            cursor_grab_active: false,
+            focused: false,
+            minimized: false,
+            theme: None,
+            position: None,
+            safe_insets: SafeInsets::default(),
+        }
+    }
+}
+
+/// A handle to a native window that isn't owned by ruxel, see `Window::parent_window`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParentWindowHandle(RawWindowHandle);
+
+// SAFETY: the handle is only read on the event loop thread when the window is created,
+// the caller of `new` guarantees it is valid until then
+unsafe impl Send for ParentWindowHandle {}
+unsafe impl Sync for ParentWindowHandle {}
+
+#[allow(dead_code)]
+impl ParentWindowHandle {
+    /// # Safety
+    ///
+    /// `handle` must be a valid window handle, and the window must outlive the ruxel window
+    /// embedded into it.
+    pub unsafe fn new(handle: RawWindowHandle) -> Self {
+        ParentWindowHandle(handle)
+    }
+
+    pub fn raw(&self) -> RawWindowHandle {
+        self.0
+    }
+}
+
+/// The size of a window, and the scale factor to convert it between logical and physical pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowResolution {
+    /// Width of the client area in logical pixels
+    pub width: u32,
+    /// Height of the client area in logical pixels
+    pub height: u32,
+    /// The scale factor the OS uses for the window, read with `base_scale_factor`
+    /// Taken from the native window as soon as it is created, then kept up to date
+    pub(crate) scale_factor: f64,
+    /// Used instead of the scale factor of the OS when set, see `set_scale_factor_override`
+    pub(crate) scale_factor_override: Option<f64>,
+    /// The fraction of the monitor size to use instead of `width` and `height`, see `percent`
+    pub(crate) monitor_fraction: Option<Vec2>,
+}
+
+#[allow(dead_code)]
+impl WindowResolution {
+    /// A resolution with the given logical size
+    /// The scale factor is `1.0` until the window is created
+    pub fn new(width: u32, height: u32) -> Self {
+        WindowResolution {
+            width,
+            height,
+            scale_factor: 1.0,
+            scale_factor_override: None,
+            monitor_fraction: None,
+        }
+    }
+
+    /// 1280x720, also known as 720p
+    pub fn hd() -> Self {
+        WindowResolution::new(1280, 720)
+    }
+
+    /// 1920x1080, also known as 1080p
+    pub fn full_hd() -> Self {
+        WindowResolution::new(1920, 1080)
+    }
+
+    /// 2560x1440, also known as 1440p
+    pub fn qhd() -> Self {
+        WindowResolution::new(2560, 1440)
+    }
+
+    /// 3840x2160, also known as 4K or 2160p
+    pub fn uhd_4k() -> Self {
+        WindowResolution::new(3840, 2160)
+    }
+
+    /// The common 16:9 resolutions from small to large, e.g. for a resolution dropdown
+    pub fn common() -> [Self; 4] {
+        [
+            WindowResolution::hd(),
+            WindowResolution::full_hd(),
+            WindowResolution::qhd(),
+            WindowResolution::uhd_4k(),
+        ]
+    }
+
+    /// A resolution relative to the monitor the window opens on, `percent(0.8, 0.8)` is 80% of
+    /// its width and height
+    ///
+    /// Resolved to a logical size when the window is created, which is then stored in `width`
+    /// and `height`. Until then, or if there is no monitor, the size is 800x600.
+    pub fn percent(width: f32, height: f32) -> Self {
+        WindowResolution {
+            monitor_fraction: Some(Vec2::new(width, height)),
+            ..WindowResolution::new(800, 600)
         }
     }
+
+    /// Turns a size from `percent` into a logical size on the given monitor
+    pub(crate) fn resolve_monitor_fraction(&mut self, monitor: Option<&Monitor>) {
+        let Some(fraction) = self.monitor_fraction.take() else {
+            return;
+        };
+        let Some(monitor) = monitor else {
+            warn!(target: LOG_TARGET,
+                "No monitor to size the window against, using {}x{}",
+                self.width, self.height
+            );
+            return;
+        };
+
+        let size = monitor.size.as_vec2() / monitor.scale_factor as f32 * fraction;
+        let size = size.round().as_uvec2().max(UVec2::ONE);
+        self.width = size.x;
+        self.height = size.y;
+    }
+
+    /// The scale factor to convert between logical and physical pixels
+    /// This is the override if one is set, otherwise the scale factor of the OS
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor_override.unwrap_or(self.scale_factor)
+    }
+
+    /// The scale factor the OS uses for the window, ignoring the override
+    pub fn base_scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    pub fn scale_factor_override(&self) -> Option<f64> {
+        self.scale_factor_override
+    }
+
+    /// Uses a fixed scale factor instead of the one of the OS, or follows the OS again with `None`
+    /// The logical size is kept, so the native window is resized to match
+    pub fn set_scale_factor_override(&mut self, scale_factor_override: Option<f64>) {
+        self.scale_factor_override = scale_factor_override;
+    }
+
+    /// The size of the client area in physical pixels
+    pub fn physical_size(&self) -> UVec2 {
+        let size =
+            LogicalSize::new(self.width, self.height).to_physical::<u32>(self.scale_factor());
+        UVec2::new(size.width, size.height)
+    }
+}
+
+/// Limits to the size of a window's client area in logical pixels
+/// `None` leaves that side unbounded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WindowResizeConstraints {
+    pub min: Option<UVec2>,
+    pub max: Option<UVec2>,
+}
+
+impl WindowResizeConstraints {
+    pub(crate) fn min_size(&self) -> Option<LogicalSize<u32>> {
+        self.min.map(|min| LogicalSize::new(min.x, min.y))
+    }
+
+    pub(crate) fn max_size(&self) -> Option<LogicalSize<u32>> {
+        self.max.map(|max| LogicalSize::new(max.x, max.y))
+    }
+}
+
+/// One size of a window icon, see `Window::icons`
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowIcon {
+    pub width: u32,
+    pub height: u32,
+    /// A flat vector of RGBA data, `width * height * 4` bytes long
+    pub data: Vec<u8>,
+}
+
+/// Whether a window is windowed or fullscreen, and on which monitor
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// A borderless window covering the whole monitor
+    BorderlessFullscreen(MonitorSelection),
+    /// Exclusive fullscreen with a video mode of the monitor
+    Fullscreen(MonitorSelection, VideoModeSelection),
+}
+
+/// Picks the video mode used for exclusive fullscreen
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoModeSelection {
+    /// The highest resolution, then bit depth, then refresh rate the monitor supports
+    #[default]
+    Best,
+    /// One of the `Monitor::video_modes` from `Monitors`, like one the player picked in a menu
+    /// If the monitor doesn't support it (anymore), the closest video mode is used instead
+    Specific(VideoMode),
+}
+
+/// Picks a monitor out of the `Monitors` resource
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MonitorSelection {
+    /// The monitor the window is currently on
+    /// Windows that are still being created use the primary monitor
+    #[default]
+    Current,
+    /// The primary monitor of the system
+    Primary,
+    /// The monitor at this index of `Monitors::monitors`
+    Index(usize),
+}
+
+/// The state of the `Window` component that was last applied to the winit window
+/// Systems compare against this to only forward actual changes to winit
+#[derive(Component)]
+pub struct CachedWindow(pub Window);
+
+/// Distances from each edge of a window to the area that is safe to draw UI in
+/// All values are in logical pixels
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SafeInsets {
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_resolutions_are_logical_sizes() {
+        let sizes =
+            WindowResolution::common().map(|resolution| (resolution.width, resolution.height));
+        assert_eq!(
+            sizes,
+            [(1280, 720), (1920, 1080), (2560, 1440), (3840, 2160)]
+        );
+
+        let mut resolution = WindowResolution::hd();
+        resolution.scale_factor = 2.0;
+        assert_eq!(resolution.physical_size(), UVec2::new(2560, 1440));
+    }
 }
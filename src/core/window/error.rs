@@ -0,0 +1,48 @@
+use bevy_ecs::prelude::Entity;
+use bevy_math::IVec2;
+use std::error::Error;
+use std::fmt;
+use std::thread::ThreadId;
+
+/// Errors returned by the fallible parts of the window module
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum WindowError {
+    /// The platform could not create the native window
+    CreationFailed(winit::error::OsError),
+    /// The entity has no native window, it was never created or has been destroyed
+    NoSuchWindow(Entity),
+    /// The platform doesn't support the operation
+    Unsupported(&'static str),
+    /// The position in physical pixels isn't on any monitor
+    OffScreen(IVec2),
+    /// Windows can only be created and used on the thread running the event loop
+    WrongThread(ThreadId),
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowError::CreationFailed(err) => write!(f, "could not create window: {err}"),
+            WindowError::NoSuchWindow(entity) => write!(f, "{:?} has no window", entity),
+            WindowError::Unsupported(operation) => {
+                write!(f, "{operation} is not supported on this platform")
+            }
+            WindowError::OffScreen(position) => {
+                write!(f, "position {position} is not on any monitor")
+            }
+            WindowError::WrongThread(thread) => {
+                write!(f, "called on {thread:?} instead of the event loop thread")
+            }
+        }
+    }
+}
+
+impl Error for WindowError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WindowError::CreationFailed(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -1,4 +1,26 @@
+use crate::core::window::components::{MonitorSelection, WindowMode};
+use crate::core::window::error::WindowError;
 use bevy_ecs::prelude::*;
+use bevy_math::{IVec2, Rect, UVec2, Vec2};
+use std::path::PathBuf;
+use winit::event::ElementState;
+use winit::keyboard::{Key, PhysicalKey};
+use winit::window::Theme;
+
+/// Events sent to the event loop from outside of it, see `ExitHandle` and `LoopControl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunnerEvent {
+    /// Run the next iteration, e.g. after `LoopControl::resume` or `ExitHandle::request_exit`
+    Wake,
+}
+
+/// Emitted once when the event loop starts, before any window is created
+///
+/// `Monitors` is filled in by then, so setup that depends on the monitors, like picking the
+/// monitor to open a window on, can read it from the first frame on.
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct EventLoopStartedEvent;
 
 /// This event is only emitted when a window receives a `CloseRequested` event.
 /// This may be from a user clicking the close button.
@@ -6,3 +28,316 @@ use bevy_ecs::prelude::*;
 pub struct CloseRequestedEvent {
     pub window_id: winit::window::WindowId,
 }
+
+/// Asks for a window to be closed, see `WindowCloseSet` for how requests can be denied
+///
+/// The window module sends this when the OS asks for a window to close,
+/// send it yourself to close a window the same way.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct WindowCloseRequestedEvent {
+    pub entity: Entity,
+}
+
+/// Closes a window with `Window::confirm_close` set, once the user confirmed it
+/// The window is despawned in `WindowCloseSet::Despawn` like any other closed window
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ConfirmWindowCloseEvent {
+    pub entity: Entity,
+}
+
+/// Send this to close every window, including the primary window, and keep running without them
+///
+/// Windows are despawned straight away, close requests can't be denied here. The `Windowless`
+/// resource is inserted so the exit condition doesn't quit the app.
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct EnterWindowlessEvent;
+
+/// Sent when the `PrimaryWindow` component moves to another window, appears or goes away
+/// Systems that target the primary window (cameras, UI, ...) should re-point at `new`
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PrimaryWindowChangedEvent {
+    pub old: Option<Entity>,
+    pub new: Option<Entity>,
+}
+
+/// Send this to make a window the primary window, e.g. for "make this the main window"
+/// The old primary window becomes a secondary window and `PrimaryWindowChangedEvent` is sent
+/// If several are sent in one frame the last one wins
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SetPrimaryWindowEvent {
+    pub entity: Entity,
+}
+
+/// Send this to refresh `WindowDiagnostics` and log it, e.g. from a debug key binding
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct DumpWindowDiagnosticsEvent;
+
+/// Send this to destroy the native window of an entity and build it again from its `Window`
+///
+/// Some changes, like certain decoration or fullscreen transitions on Wayland, only apply to new
+/// windows. The entity, position and size stay the same. The window is announced with a
+/// `WindowClosingEvent` and destroyed like a closing window, so renderers can drop their surface
+/// first. It is built again after the frame it was destroyed in, with a `WindowCreatedEvent`.
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct RecreateWindowEvent {
+    pub entity: Entity,
+}
+
+/// Send this to close every window except the primary window
+/// A close request is sent for each of them, so they can be denied like any other close
+#[derive(Event)]
+pub struct CloseSecondaryWindowsEvent;
+
+/// Send this to bring a window to the front and give it keyboard focus
+///
+/// Some platforms don't let apps take focus by themselves,
+/// the window requests the user's attention instead (like flashing in the taskbar).
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct FocusWindowEvent {
+    pub entity: Entity,
+}
+
+/// Send this to change the opacity of a whole window, for example to fade tool windows in and out
+/// This is different from `Window::transparent`, which makes transparent pixels see-through
+///
+/// The opacity is clamped to `0.0..=1.0`.
+/// Only supported on Windows, where the window is made a layered window the first time. winit has
+/// no opacity API, so other platforms only log that it is unsupported.
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct SetWindowOpacityEvent {
+    pub entity: Entity,
+    pub opacity: f32,
+}
+
+/// Send this to change the title, size or position of a window, see `WindowCommand`
+#[allow(dead_code)]
+#[derive(Event, Clone, Debug)]
+pub struct WindowCommandEvent {
+    pub entity: Entity,
+    pub command: WindowCommand,
+}
+
+/// A change to a window, applied by `u_apply_window_commands`
+///
+/// The commands of a frame are applied in a fixed order no matter the order they were sent in:
+/// the title, then the size, then the position. Moving comes last so `Center` uses the new
+/// size. Of several commands of the same kind for a window, the last one wins.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowCommand {
+    SetTitle(String),
+    /// The new size of the client area in logical pixels
+    Resize(UVec2),
+    /// The new top left corner of the window, including decorations, in physical pixels
+    SetPosition(IVec2),
+    /// Centers the window on the monitor it is on
+    Center,
+}
+
+/// Emitted after the native window of an entity has been created
+/// Since windows are created between frames, this can be read in the next update
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct WindowCreatedEvent {
+    pub entity: Entity,
+}
+
+/// Emitted when the native window of an entity could not be created, even after retrying
+/// See `WindowPlugin::window_creation_attempts`, the entity keeps its `Window` component
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct WindowCreationFailedEvent {
+    pub entity: Entity,
+    /// The error of the last attempt
+    pub error: WindowError,
+}
+
+/// Emitted when the native window of an entity is about to be destroyed
+/// Its winit window is still alive until `WindowCloseSet::Destroy` runs in the same frame, or in the
+/// next one with `WindowPlugin::deferred_destroy`
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct WindowClosingEvent {
+    pub entity: Entity,
+}
+
+/// Emitted after the native window of an entity has been destroyed
+/// This also happens for every window still open when the event loop exits
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct WindowDestroyedEvent {
+    pub entity: Entity,
+}
+
+/// Emitted when a window is resized
+/// When `WindowPlugin::coalesce_resize_events` is set, only the last size of each frame is sent
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct WindowResizedEvent {
+    pub entity: Entity,
+    /// The new size of the client area in physical pixels, as reported by the OS
+    pub physical_size: UVec2,
+    /// The new size of the client area in logical pixels
+    /// Converted with `scale_factor`, so it follows `WindowResolution::scale_factor_override`
+    pub logical_size: UVec2,
+    /// The scale factor of the window at the time of the resize, see `Window::scale_factor`
+    pub scale_factor: f64,
+}
+
+/// Emitted when the mode of a window changed, either through `Window::mode` or by the OS
+/// The user can toggle fullscreen outside of the app, like with the green button on macOS,
+/// `Window::mode` is updated to match before this is sent
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct WindowModeChangedEvent {
+    pub entity: Entity,
+    pub mode: WindowMode,
+}
+
+/// Send this to move a window onto another monitor, e.g. from a hotkey
+///
+/// The window keeps its position relative to the monitor it was on, scaled to the size of the new
+/// monitor, and is centered if it wasn't on any monitor. A monitor that is gone falls back to the
+/// primary monitor, see `Monitors::resolve`. Emits a `WindowMovedEvent` once the window moved.
+#[allow(dead_code)]
+#[derive(Event, Clone, Debug)]
+pub struct MoveWindowToMonitorEvent {
+    pub entity: Entity,
+    pub monitor: MonitorSelection,
+}
+
+/// Emitted when a window moves on the desktop
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct WindowMovedEvent {
+    pub entity: Entity,
+    /// The new outer position of the window in physical pixels
+    pub position: IVec2,
+}
+
+/// Emitted when the scale factor, its override or the `ui_scale` of a window changes
+/// Changes of the OS scale factor are not emitted while an override is set
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct ScaleFactorChangedEvent {
+    pub entity: Entity,
+    /// The scale factor of the window, see `Window::scale_factor`
+    pub scale_factor: f64,
+    /// The scale factor combined with `Window::ui_scale`
+    pub effective_scale_factor: f64,
+}
+
+/// Send this to confine the cursor to part of a window, like a viewport panel in an editor
+///
+/// `rect` is in logical pixels relative to the top left of the window, `None` releases the
+/// cursor again (back to `Window::cursor_grab_mode`). Only supported on Windows, where the
+/// confinement pauses while the window is not focused and follows it when it moves or is resized.
+/// Other platforms confine the cursor to the whole window instead.
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SetCursorConfineRegionEvent {
+    pub entity: Entity,
+    pub rect: Option<Rect>,
+}
+
+/// Emitted when the cursor moves inside a window
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct CursorMovedEvent {
+    pub entity: Entity,
+    /// Position of the cursor in logical pixels, relative to the top left of the window
+    pub position: Vec2,
+    /// How far the cursor moved since the previous event for this window, in logical pixels
+    /// This is zero for the first move after the cursor enters the window
+    pub delta: Vec2,
+}
+
+/// Emitted when a key is pressed or released while a window has focus
+///
+/// Keys held down repeat with `repeat: true`, unless `WindowPlugin::filter_key_repeat` is set.
+/// Use `TextInputEvent` for typing text, it isn't affected by the filter.
+#[allow(dead_code)]
+#[derive(Event, Clone, Debug)]
+pub struct KeyboardInputEvent {
+    pub entity: Entity,
+    /// The key with the keyboard layout applied, e.g. for shortcuts named after letters
+    pub logical_key: Key,
+    /// The position of the key on the keyboard, no matter the layout
+    /// Bind movement to this, so WASD is in the same place on an AZERTY keyboard
+    pub physical_key: PhysicalKey,
+    pub state: ElementState,
+    /// Whether this is a repeat of a key that is held down
+    pub repeat: bool,
+}
+
+/// Emitted when an action of `KeyBindings` is pressed or released, see `Actions`
+#[allow(dead_code)]
+#[derive(Event, Clone, Debug)]
+pub struct ActionEvent {
+    pub action: String,
+    pub state: ElementState,
+}
+
+/// Emitted when the keyboard layout of the OS changed, e.g. to refresh the key names shown for
+/// `KeyBindings`
+///
+/// winit has no event for this, so a change is noticed once a pressed key produces a different
+/// character than it did before. That makes the event arrive with the first key press after the
+/// change. It is never sent on platforms that don't report the key without modifiers, like the web.
+#[allow(dead_code)]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct KeyboardLayoutChangedEvent;
+
+/// Emitted with the text a key press typed, including repeats of held keys
+#[allow(dead_code)]
+#[derive(Event, Clone, Debug)]
+pub struct TextInputEvent {
+    pub entity: Entity,
+    pub text: String,
+}
+
+/// Emitted when the theme of a window changes
+/// This happens when the OS theme changes and the window has no preferred theme
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct ThemeChangedEvent {
+    pub entity: Entity,
+    pub theme: Theme,
+}
+
+/// Send this to request a screenshot of a window
+///
+/// The window module doesn't render anything, so it is the renderer that handles this:
+/// at the end of the frame the event was read in, it copies the presented image of the window
+/// and responds with a `ScreenshotCapturedEvent` for the same entity and path.
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct ScreenshotRequestedEvent {
+    pub entity: Entity,
+    /// Where the screenshot should be saved
+    pub path: PathBuf,
+}
+
+/// Sent by the renderer once a requested screenshot has been captured
+#[allow(dead_code)]
+#[derive(Event)]
+pub struct ScreenshotCapturedEvent {
+    pub entity: Entity,
+    /// The path from the `ScreenshotRequestedEvent`
+    pub path: PathBuf,
+    /// Width of the image in physical pixels
+    pub width: u32,
+    /// Height of the image in physical pixels
+    pub height: u32,
+    /// A flat vector of 8 bit RGBA data, row by row from the top left
+    /// Always `width * height * 4` bytes long
+    pub data: Vec<u8>,
+}
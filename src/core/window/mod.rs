@@ -1,25 +1,74 @@
 pub mod resources;
 
+pub mod backend;
 pub mod components;
+pub mod error;
 pub mod events;
 pub mod icon;
+pub mod schedules;
 pub mod systems;
+#[cfg(test)]
+mod testing;
 
-use crate::core::window::components::{PrimaryWindow, Window};
-use crate::core::window::events::CloseRequestedEvent;
-use crate::core::window::resources::{PrimaryWindowCount, WinitWindows};
+use crate::core::window::backend::WindowBackend;
+use crate::core::window::components::{
+    CachedWindow, PrimaryWindow, Window, WindowMode, WindowResolution,
+};
+use crate::core::window::events::{
+    ActionEvent, CloseRequestedEvent, CloseSecondaryWindowsEvent, ConfirmWindowCloseEvent,
+    CursorMovedEvent, DumpWindowDiagnosticsEvent, EnterWindowlessEvent, EventLoopStartedEvent,
+    FocusWindowEvent, KeyboardInputEvent, KeyboardLayoutChangedEvent, MoveWindowToMonitorEvent,
+    PrimaryWindowChangedEvent, RecreateWindowEvent, RunnerEvent, ScaleFactorChangedEvent,
+    ScreenshotCapturedEvent, ScreenshotRequestedEvent, SetCursorConfineRegionEvent,
+    SetPrimaryWindowEvent, SetWindowOpacityEvent, TextInputEvent, ThemeChangedEvent,
+    WindowCloseRequestedEvent, WindowClosingEvent, WindowCommandEvent, WindowCreatedEvent,
+    WindowCreationFailedEvent, WindowDestroyedEvent, WindowModeChangedEvent, WindowMovedEvent,
+    WindowResizedEvent,
+};
+use crate::core::window::resources::{
+    Actions, ButtonInput, ClosingWindows, ControlFlowSettings, CustomExitCondition,
+    DeferredPrimaryWindow, ExitGrace, ExitHandle, FrameTimings, InputCapture, KeyBindings,
+    LastInputTime, LoopControl, Modifiers, Monitors, PrimaryWindowEntity, RunnerSettings,
+    ScreensaverInhibitor, WindowCloseDenied, WindowConfig, WindowCounts, WindowDiagnostics,
+    WindowNames, WindowSettings, WindowStartupTiming, Windowless, Windows, WinitWindows,
+};
+use crate::core::window::schedules::{Paused, Shutdown, WindowClose, WindowCloseSet};
 use crate::core::window::systems::{
-    pu_exit_on_all_closed, pu_exit_on_primary_closed, u_close_windows, u_despawn_windows,
-    u_primary_window_check,
+    pr_update_actions, ps_spawn_deferred_primary_window, pu_exit_on_all_closed,
+    pu_exit_on_custom_condition, pu_exit_on_primary_closed, pu_exit_on_request,
+    u_announce_closing_windows, u_announce_recreated_windows, u_apply_window_commands,
+    u_apply_window_config, u_close_on_esc, u_close_secondary_windows, u_close_windows,
+    u_count_windows, u_despawn_windows, u_dump_window_diagnostics, u_enter_windowless,
+    u_focus_window, u_forward_close_requests, u_index_window_names, u_inhibit_screensaver,
+    u_move_windows_to_monitor, u_primary_window_check, u_run_window_close,
+    u_set_cursor_confine_region, u_set_primary_window, u_set_window_opacity,
+    u_track_primary_window, u_update_cursor_grab, u_update_cursor_hittest,
+    u_update_enabled_buttons, u_update_ime, u_update_safe_insets, u_update_scale_factor_override,
+    u_update_theme, u_update_title, u_update_ui_scale, u_update_visibility,
+    u_update_window_constraints, u_update_window_mode, u_update_window_size,
 };
 use bevy_app::prelude::*;
 use bevy_app::{AppExit, PluginsState};
 use bevy_ecs::event::ManualEventReader;
 use bevy_ecs::prelude::*;
-use bevy_ecs::system::SystemState;
-use log::{error, info};
-use winit::event::{Event, StartCause, WindowEvent};
-use winit::event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget};
+use bevy_ecs::system::{SystemParam, SystemState};
+use bevy_math::{IVec2, UVec2, Vec2};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, Event, MouseButton, StartCause, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopWindowTarget};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::CursorGrabMode;
+
+/// Log target used by everything in the window module, so its output can be filtered separately
+/// (e.g. `RUST_LOG=ruxel::window=warn`)
+pub const LOG_TARGET: &str = "ruxel::window";
 
 /// The plugin which adds a window and associated systems to the app.
 ///
@@ -30,56 +79,368 @@ pub struct WindowPlugin {
     /// Can be `None` if no primary window is desired
     /// Set primary window parameters here
     pub primary_window: Option<Window>,
+    /// Whether to wait until the first frame to spawn the primary window
+    ///
+    /// When `true`, `primary_window` is put into the `DeferredPrimaryWindow` resource instead of
+    /// being spawned straight away. `Startup` systems can change or clear it, for example
+    /// from a loaded settings file, and it is spawned in `PostStartup`.
+    /// The exit condition is not checked until the primary window has been spawned.
+    pub defer_primary_window: bool,
     /// The condition at which the event loop will exit.
     ///
     /// See `ExitCondition` for more information.
     pub exit_condition: ExitCondition,
+    /// How often the app updates
+    ///
+    /// Systems can change this while running through the `ControlFlowSettings` resource.
+    /// While there are no windows, e.g. with `ExitCondition::DontExit`, the app only updates on
+    /// events and goes back to this mode once a window is spawned.
+    pub update_mode: UpdateMode,
+    /// Whether to only send `WindowResizedEvent` for the last size of each frame
+    ///
+    /// While the user drags the border of a window the OS sends many resizes per frame,
+    /// coalescing them saves the renderer from recreating its surface for every single one.
+    pub coalesce_resize_events: bool,
+    /// Whether to drop `KeyboardInputEvent`s of keys repeating while held down
+    /// Simplifies gameplay code that only cares about presses, `TextInputEvent` still repeats
+    pub filter_key_repeat: bool,
+    /// Whether to keep new windows hidden until the first frame after their creation is done
+    ///
+    /// This avoids showing a blank white or black window while the first frame is rendered.
+    /// Windows with `visible: false` stay hidden either way.
+    pub wait_for_first_frame: bool,
+    /// Which events wake the app up while it waits in `UpdateMode::Reactive`
+    ///
+    /// Filtered events are still recorded and read by the next update, e.g. the cursor
+    /// position is up to date once a key press or the `wait` timeout runs a frame.
+    pub reactive_wake_filter: WakeFilter,
+    /// Whether to catch panics of systems instead of aborting inside the event loop
+    ///
+    /// A panicking frame is logged and the app exits through `AppExit`, running `Shutdown`
+    /// like any other exit. The panic message is still printed by the panic hook.
+    pub catch_update_panics: bool,
+    /// How often creating a native window is tried, one attempt per frame
+    ///
+    /// The first window can fail to open while drivers or the compositor are still starting.
+    /// `WindowCreationFailedEvent` is sent once every attempt failed.
+    pub window_creation_attempts: u32,
+    /// Whether the app keeps updating while every window is minimized
+    ///
+    /// Games can turn this off to pause while minimized, servers and background tasks keep it
+    /// on. While paused only the `Paused` schedule runs, so windows can still be closed from the
+    /// taskbar. Either way a renderer should skip presenting to minimized windows, see
+    /// `Window::minimized`.
+    pub update_when_minimized: bool,
+    /// Whether a primary window left at the default size is sized relative to its monitor
+    ///
+    /// The fixed default of 800x600 logical pixels is tiny on a large 4K monitor and huge on a
+    /// small laptop. When set, it becomes two thirds of the monitor, see
+    /// `WindowResolution::percent`. Primary windows with any other size are left alone.
+    pub monitor_relative_default_size: bool,
+    /// How many frames still run after `AppExit` before the app exits
+    ///
+    /// Systems see the exit coming through `ExitGrace` and can set `ExitGrace::ready` once they
+    /// are done, e.g. after finishing a save, to exit before all frames ran. The frame count is
+    /// a safety cap, so an app that never gets ready still exits. `0` exits straight away.
+    pub exit_grace_frames: u32,
+    /// Whether pressing Escape requests the focused window to close, handy while prototyping
+    /// The request goes through `WindowCloseSet` like any other, so it can still be denied
+    pub close_on_esc: bool,
+    /// Whether every window ignores the DPI scaling of the OS, so logical pixels are physical ones
+    ///
+    /// Meant for pixel art, where one pixel of the game should be exactly one pixel of the
+    /// screen. The scale factor override of every window is kept at `1.0`, so sizes, cursor
+    /// positions and resize events are all in physical pixels. winit already makes the app DPI
+    /// aware, so the OS never stretches the windows instead. The tradeoff is that windows and
+    /// anything drawn at a fixed size look small on HiDPI monitors, scale them by hand if needed.
+    pub ignore_dpi_scaling: bool,
+    /// Whether native windows are destroyed one frame after `WindowClosingEvent` instead of in
+    /// the same frame
+    ///
+    /// This gives a renderer a whole frame to drop the surface of a closing window while its
+    /// winit window is still alive. `WindowDestroyedEvent` is sent once it is actually gone.
+    pub deferred_destroy: bool,
 }
 
+/// The fraction of the monitor the primary window covers with `monitor_relative_default_size`
+const DEFAULT_MONITOR_FRACTION: f32 = 2.0 / 3.0;
+
 impl Default for WindowPlugin {
     fn default() -> Self {
         WindowPlugin {
             primary_window: Some(Window::default()),
+            defer_primary_window: false,
             exit_condition: ExitCondition::default(),
+            update_mode: UpdateMode::default(),
+            coalesce_resize_events: true,
+            filter_key_repeat: false,
+            wait_for_first_frame: false,
+            reactive_wake_filter: WakeFilter::default(),
+            catch_update_panics: false,
+            window_creation_attempts: 3,
+            update_when_minimized: true,
+            monitor_relative_default_size: false,
+            exit_grace_frames: 0,
+            close_on_esc: false,
+            ignore_dpi_scaling: false,
+            deferred_destroy: false,
         }
     }
 }
 
 impl Plugin for WindowPlugin {
     fn build(&self, app: &mut App) {
+        self.build_with_backend::<WinitWindows>(app);
+
+        // Insert resources
+        // Event loop created here
+        let event_loop = EventLoopBuilder::<RunnerEvent>::with_user_event()
+            .build()
+            .unwrap();
+        app.insert_resource(ExitHandle::new(event_loop.create_proxy()));
+        app.insert_resource(LoopControl::new(event_loop.create_proxy()));
+        app.insert_non_send_resource(event_loop);
+
+        // Set event loop runner
+        app.set_runner(runner);
+    }
+}
+
+impl WindowPlugin {
+    /// The primary window to spawn, with `monitor_relative_default_size` applied
+    fn initial_primary_window(&self) -> Option<Window> {
+        let mut window = self.primary_window.clone()?;
+        if self.monitor_relative_default_size && window.resolution == Window::default().resolution {
+            window.resolution =
+                WindowResolution::percent(DEFAULT_MONITOR_FRACTION, DEFAULT_MONITOR_FRACTION);
+        }
+        Some(window)
+    }
+
+    /// Everything except the event loop and the runner, with windows closed through `B`
+    ///
+    /// `WinitWindows` is inserted either way, since most systems talk to winit directly.
+    /// Without an event loop it never has any windows, so those systems do nothing.
+    fn build_with_backend<B: WindowBackend>(&self, app: &mut App) {
         // Register events
         app.add_event::<CloseRequestedEvent>();
+        app.add_event::<PrimaryWindowChangedEvent>();
+        app.add_event::<SetPrimaryWindowEvent>();
+        app.add_event::<DumpWindowDiagnosticsEvent>();
+        app.add_event::<WindowCommandEvent>();
+        app.add_event::<MoveWindowToMonitorEvent>();
+        app.add_event::<WindowCloseRequestedEvent>();
+        app.add_event::<ConfirmWindowCloseEvent>();
+        app.add_event::<EnterWindowlessEvent>();
+        app.add_event::<RecreateWindowEvent>();
+        app.add_event::<CloseSecondaryWindowsEvent>();
+        app.add_event::<FocusWindowEvent>();
+        app.add_event::<SetWindowOpacityEvent>();
+        app.add_event::<CursorMovedEvent>();
+        app.add_event::<SetCursorConfineRegionEvent>();
+        app.add_event::<KeyboardInputEvent>();
+        app.add_event::<KeyboardLayoutChangedEvent>();
+        app.add_event::<EventLoopStartedEvent>();
+        app.add_event::<ActionEvent>();
+        app.add_event::<TextInputEvent>();
+        app.add_event::<ThemeChangedEvent>();
+        app.add_event::<WindowCreatedEvent>();
+        app.add_event::<WindowCreationFailedEvent>();
+        app.add_event::<WindowClosingEvent>();
+        app.add_event::<WindowDestroyedEvent>();
+        app.add_event::<WindowMovedEvent>();
+        app.add_event::<WindowModeChangedEvent>();
+        app.add_event::<WindowResizedEvent>();
+        app.add_event::<ScaleFactorChangedEvent>();
+        app.add_event::<ScreenshotRequestedEvent>();
+        app.add_event::<ScreenshotCapturedEvent>();
+
+        // Register schedules
+        app.init_schedule(Shutdown);
+        app.init_schedule(Paused);
+        app.init_schedule(WindowClose);
 
-        // If a primary window is specified, spawn the entity with the window
-        if let Some(primary_window) = &self.primary_window {
-            app.world
-                .spawn(primary_window.clone())
-                .insert(PrimaryWindow);
+        if self.defer_primary_window {
+            // Leave the primary window for startup systems to configure, it is spawned later
+            app.insert_resource(DeferredPrimaryWindow(self.initial_primary_window()));
+            app.add_systems(PostStartup, ps_spawn_deferred_primary_window);
+        } else if let Some(primary_window) = self.initial_primary_window() {
+            // If a primary window is specified, spawn the entity with the window
+            app.world.spawn(primary_window).insert(PrimaryWindow);
         }
 
-        // Add systems to exit the event loop when the condition is met
+        // Exits requested through an `ExitHandle` are honored whatever the exit condition
+        app.add_systems(PostUpdate, pu_exit_on_request);
+        app.add_systems(Paused, pu_exit_on_request);
+
+        // Add systems to exit the event loop when the condition is met, see `exit_checks_enabled`
         match self.exit_condition {
             ExitCondition::OnPrimaryClosed => {
-                app.add_systems(PostUpdate, pu_exit_on_primary_closed);
+                app.add_systems(
+                    PostUpdate,
+                    pu_exit_on_primary_closed.run_if(exit_checks_enabled),
+                );
+                app.add_systems(
+                    Paused,
+                    pu_exit_on_primary_closed
+                        .after(u_run_window_close)
+                        .run_if(exit_checks_enabled),
+                );
             }
             ExitCondition::OnAllClosed => {
-                app.add_systems(PostUpdate, pu_exit_on_all_closed);
+                app.add_systems(
+                    PostUpdate,
+                    pu_exit_on_all_closed.run_if(exit_checks_enabled),
+                );
+                app.add_systems(
+                    Paused,
+                    pu_exit_on_all_closed
+                        .after(u_run_window_close)
+                        .run_if(exit_checks_enabled),
+                );
+            }
+            ExitCondition::Custom(ref predicate) => {
+                app.insert_resource(CustomExitCondition(predicate.clone()));
+                app.add_systems(
+                    PostUpdate,
+                    pu_exit_on_custom_condition.run_if(exit_checks_enabled),
+                );
+                app.add_systems(
+                    Paused,
+                    pu_exit_on_custom_condition
+                        .after(u_run_window_close)
+                        .run_if(exit_checks_enabled),
+                );
             }
             ExitCondition::DontExit => {}
         }
 
         // Insert resources
-        app.insert_non_send_resource(EventLoop::new().unwrap()); // Event loop created here
         app.insert_non_send_resource(WinitWindows::default());
-        app.insert_resource(PrimaryWindowCount::default());
+        app.insert_resource(WindowCounts::default());
+        app.insert_resource(PrimaryWindowEntity::default());
+        app.insert_resource(FrameTimings::default());
+        app.insert_resource(LastInputTime::default());
+        app.insert_resource(Monitors::default());
+        app.insert_resource(WindowCloseDenied::default());
+        app.insert_resource(ClosingWindows::default());
+        app.insert_resource(WindowNames::default());
+        app.insert_resource(Windows::default());
+        app.init_resource::<WindowSettings>();
+        app.insert_resource(WindowStartupTiming::new());
+        app.insert_resource(Modifiers::default());
+        app.insert_resource(ButtonInput::<KeyCode>::default());
+        app.insert_resource(ButtonInput::<MouseButton>::default());
+        app.init_resource::<KeyBindings>();
+        app.insert_resource(Actions::default());
+        app.insert_resource(InputCapture::default());
+        app.insert_resource(ExitGrace::default());
+        app.insert_resource(WindowDiagnostics {
+            exit_condition: self.exit_condition.name().to_string(),
+            ..Default::default()
+        });
+        app.insert_resource(ScreensaverInhibitor::default());
+        app.insert_resource(ControlFlowSettings::new(self.update_mode));
+        app.insert_resource(RunnerSettings {
+            coalesce_resize_events: self.coalesce_resize_events,
+            filter_key_repeat: self.filter_key_repeat,
+            wait_for_first_frame: self.wait_for_first_frame,
+            reactive_wake_filter: self.reactive_wake_filter,
+            catch_update_panics: self.catch_update_panics,
+            window_creation_attempts: self.window_creation_attempts,
+            update_when_minimized: self.update_when_minimized,
+            exit_grace_frames: self.exit_grace_frames,
+            ignore_dpi_scaling: self.ignore_dpi_scaling,
+            deferred_destroy: self.deferred_destroy,
+        });
 
         // Add systems
+        app.add_systems(Update, u_set_primary_window.before(u_primary_window_check));
         app.add_systems(Update, u_primary_window_check);
-        app.add_systems(Update, u_close_windows);
-        app.add_systems(Update, u_despawn_windows);
+        app.configure_sets(
+            WindowClose,
+            (
+                WindowCloseSet::Request,
+                WindowCloseSet::Veto,
+                WindowCloseSet::Despawn,
+                WindowCloseSet::Announce,
+                WindowCloseSet::Destroy,
+            )
+                .chain(),
+        );
+        app.add_systems(
+            WindowClose,
+            u_announce_closing_windows::<B>.in_set(WindowCloseSet::Announce),
+        );
+        app.add_systems(
+            WindowClose,
+            u_announce_recreated_windows::<B>.in_set(WindowCloseSet::Announce),
+        );
+        app.add_systems(
+            WindowClose,
+            u_close_windows::<B>.in_set(WindowCloseSet::Destroy),
+        );
+        app.add_systems(
+            WindowClose,
+            (u_forward_close_requests::<B>, u_close_secondary_windows)
+                .in_set(WindowCloseSet::Request),
+        );
+        if self.close_on_esc {
+            app.add_systems(WindowClose, u_close_on_esc.in_set(WindowCloseSet::Request));
+        }
+        app.add_systems(
+            WindowClose,
+            (u_despawn_windows, u_enter_windowless).in_set(WindowCloseSet::Despawn),
+        );
+        app.add_systems(WindowClose, u_count_windows.after(WindowCloseSet::Destroy));
+        app.add_systems(Update, u_run_window_close);
+        app.add_systems(
+            Update,
+            u_track_primary_window
+                .after(u_primary_window_check)
+                .after(u_run_window_close),
+        );
 
-        // Set event loop runner
-        app.set_runner(runner);
+        // While the app is paused, windows can still be closed and the app can exit
+        app.add_systems(Paused, (u_run_window_close, u_track_primary_window).chain());
+
+        app.add_systems(PreUpdate, pr_update_actions);
+        app.add_systems(Update, u_focus_window);
+        app.add_systems(Update, u_index_window_names.after(u_run_window_close));
+        app.add_systems(Update, u_set_window_opacity);
+        app.add_systems(Update, u_update_cursor_hittest);
+        app.add_systems(Update, u_update_cursor_grab::<B>);
+        app.add_systems(
+            Update,
+            u_set_cursor_confine_region.after(u_update_cursor_grab::<B>),
+        );
+        app.add_systems(Update, u_update_safe_insets);
+        app.add_systems(Update, u_update_theme::<B>);
+        app.add_systems(Update, u_update_window_mode::<B>);
+        app.add_systems(Update, u_update_visibility::<B>);
+        app.add_systems(Update, u_update_enabled_buttons::<B>);
+        app.add_systems(Update, u_update_title.after(u_apply_window_commands::<B>));
+        app.add_systems(Update, u_apply_window_commands::<B>);
+        app.add_systems(
+            Update,
+            u_move_windows_to_monitor::<B>.after(u_apply_window_commands::<B>),
+        );
+        app.add_systems(Update, u_dump_window_diagnostics);
+        app.add_systems(
+            Update,
+            u_apply_window_config.run_if(resource_exists_and_changed::<WindowConfig>),
+        );
+        app.add_systems(
+            Update,
+            u_update_window_size::<B>
+                .after(u_apply_window_commands::<B>)
+                .after(u_apply_window_config),
+        );
+        app.add_systems(Update, u_update_window_constraints::<B>);
+        app.add_systems(Update, u_update_ui_scale);
+        app.add_systems(Update, u_update_scale_factor_override);
+        app.add_systems(Update, u_update_ime);
+        app.add_systems(Update, u_inhibit_screensaver);
     }
 }
 
@@ -94,15 +455,18 @@ fn runner(mut app: App) {
     // Get the event loop from resources
     let event_loop = app
         .world
-        .remove_non_send_resource::<EventLoop<()>>()
+        .remove_non_send_resource::<EventLoop<RunnerEvent>>()
         .unwrap();
 
-    // System state of added window component
-    // We will use this in the event loop to create any new windows that were added
-    let mut create_windows_system_state: SystemState<(
-        Query<(Entity, &Window), Added<Window>>,
-        NonSendMut<WinitWindows>,
-    )> = SystemState::from_world(&mut app.world);
+    // Start with the configured update mode, after that the runner sets it every iteration
+    let control_flow = app
+        .world
+        .resource_mut::<ControlFlowSettings>()
+        .next_control_flow(true);
+    event_loop.set_control_flow(control_flow);
+
+    // Runs the frames and creates any new windows that were added in them
+    let mut stepper = FrameStepper::<WinitWindows>::new(&mut app);
 
     // Event reader to read any app exit events
     let mut app_exit_event_reader = ManualEventReader::<AppExit>::default();
@@ -110,87 +474,986 @@ fn runner(mut app: App) {
     // ! Temporary fix of extra AboutToWait events on windows
     let mut exited = false;
 
-    let event_handler = move |event: Event<()>, window_target: &EventLoopWindowTarget<()>| {
-        // Close the event loop if there is any app exit events
-        if let Some(app_exit_events) = app.world.get_resource::<Events<AppExit>>() {
-            if app_exit_event_reader.read(app_exit_events).last().is_some() {
-                window_target.exit();
-                exited = true;
-                return;
-            }
-        }
+    // Frames left until the app exits anyway once `AppExit` was sent, see `exit_grace_frames`
+    let mut exit_grace_left = None;
 
-        match event {
-            // Start of the event loop
-            Event::NewEvents(StartCause::Init) => {
-                // Create any new windows
-                let (query, winit_windows) = create_windows_system_state.get_mut(&mut app.world);
-                create_windows(query, winit_windows, window_target);
-                create_windows_system_state.apply(&mut app.world);
+    // Whether an event since the last frame should run the next one, see `WakeFilter`
+    let wake_filter = app.world.resource::<RunnerSettings>().reactive_wake_filter;
+    let catch_update_panics = app.world.resource::<RunnerSettings>().catch_update_panics;
+    let mut wake_requested = true;
+
+    // The key each physical key produced the last time, see `KeyboardLayoutChangedEvent`
+    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+    let mut keyboard_layout = HashMap::new();
+
+    let event_handler =
+        move |event: Event<RunnerEvent>, window_target: &EventLoopWindowTarget<RunnerEvent>| {
+            // Close the event loop if there is any app exit events
+            if let Some(app_exit_events) = app.world.get_resource::<Events<AppExit>>() {
+                if app_exit_event_reader.read(app_exit_events).last().is_some()
+                    && start_exit_grace(&mut app.world, &mut exit_grace_left)
+                {
+                    window_target.exit();
+                    exited = true;
+                    return;
+                }
             }
-            // Send a close requested event so systems can drop the Window and despawn windows
-            Event::WindowEvent {
-                window_id,
-                event: WindowEvent::CloseRequested,
-            } => {
-                // Close window
-                app.world.send_event(CloseRequestedEvent { window_id });
+
+            wake_requested |= wake_filter.wakes(&event);
+
+            if let Event::WindowEvent {
+                event: ref window_event,
+                ..
+            } = event
+            {
+                record_user_input(&mut app.world, window_event);
             }
-            // This is where the frame happens
-            Event::AboutToWait => {
+
+            match event {
+                // Start of the event loop
+                Event::NewEvents(StartCause::Init) => {
+                    // The launcher can pass a token so the first window takes focus on Wayland and X11,
+                    // child processes must not inherit it
+                    #[cfg(target_os = "linux")]
+                    {
+                        use winit::platform::startup_notify::{self, EventLoopExtStartupNotify};
+                        let activation_token = window_target.read_token_from_env();
+                        startup_notify::reset_activation_token_env();
+                        app.world
+                            .non_send_resource_mut::<WinitWindows>()
+                            .activation_tokens
+                            .launcher = activation_token;
+                    }
+
+                    // Now that there is an event loop the monitors can be listed
+                    let handles: Vec<_> = window_target.available_monitors().collect();
+                    let primary = window_target.primary_monitor();
+                    let monitors = Monitors::from_handles(&handles, primary.as_ref());
+                    stepper.start(&mut app, window_target, monitors);
+                }
+                // Send a close requested event so systems can drop the Window and despawn windows
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::CloseRequested,
+                } => {
+                    // Close window
+                    app.world.send_event(CloseRequestedEvent { window_id });
+                }
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::CursorMoved { position, .. },
+                } => {
+                    let winit_windows = app.world.non_send_resource::<WinitWindows>();
+                    let Some(entity) = winit_windows.entity_for_window_id(window_id) else {
+                        return;
+                    };
+
+                    let position = logical_cursor_position(&app.world, entity, position, || {
+                        winit_windows
+                            .get_window(entity)
+                            .map_or(1.0, |winit_window| winit_window.scale_factor())
+                    });
+                    let mut winit_windows = app.world.non_send_resource_mut::<WinitWindows>();
+
+                    // The first move after entering the window has no previous position to compare to
+                    let delta = winit_windows
+                        .cursor_positions
+                        .insert(entity, position)
+                        .map_or(Vec2::ZERO, |last_position| position - last_position);
+
+                    if !app.world.resource::<InputCapture>().enabled {
+                        return;
+                    }
+                    app.world.send_event(CursorMovedEvent {
+                        entity,
+                        position,
+                        delta,
+                    });
+                }
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::CursorLeft { .. },
+                } => {
+                    let mut winit_windows = app.world.non_send_resource_mut::<WinitWindows>();
+                    if let Some(entity) = winit_windows.entity_for_window_id(window_id) {
+                        winit_windows.cursor_positions.remove(&entity);
+                    }
+                }
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::KeyboardInput { event, .. },
+                } => {
+                    let winit_windows = app.world.non_send_resource::<WinitWindows>();
+                    let Some(entity) = winit_windows.entity_for_window_id(window_id) else {
+                        return;
+                    };
+
+                    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+                    {
+                        use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+                        record_keyboard_layout(
+                            &mut app.world,
+                            &mut keyboard_layout,
+                            (event.physical_key, event.state),
+                            event.key_without_modifiers(),
+                        );
+                    }
+                    let text = event.text.as_deref().map(str::to_string);
+                    dispatch_key_input(
+                        &mut app.world,
+                        KeyboardInputEvent {
+                            entity,
+                            logical_key: event.logical_key,
+                            physical_key: event.physical_key,
+                            state: event.state,
+                            repeat: event.repeat,
+                        },
+                        text,
+                    );
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::MouseInput { state, button, .. },
+                    ..
+                } => {
+                    update_button_input(&mut app.world, button, state);
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ModifiersChanged(modifiers),
+                    ..
+                } => {
+                    app.world
+                        .resource_mut::<Modifiers>()
+                        .set_state(modifiers.state());
+                }
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::Resized(size),
+                } => {
+                    let winit_windows = app.world.non_send_resource::<WinitWindows>();
+                    let Some(entity) = winit_windows.entity_for_window_id(window_id) else {
+                        return;
+                    };
+
+                    // Some platforms only tell about minimizing through a resize to zero
+                    let minimized = winit_windows
+                        .get_window(entity)
+                        .and_then(|winit_window| winit_window.is_minimized())
+                        .unwrap_or(size.width == 0 || size.height == 0);
+                    #[cfg(windows)]
+                    winit_windows.apply_cursor_confine_region(entity);
+                    set_minimized(&mut app.world, entity, minimized);
+
+                    if app
+                        .world
+                        .resource::<RunnerSettings>()
+                        .coalesce_resize_events
+                    {
+                        // Only the last size matters, it is applied right before the next frame
+                        stepper.queue_resize(entity, size);
+                    } else {
+                        resize_window::<WinitWindows>(&mut app.world, entity, size);
+                    }
+                }
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::Occluded(occluded),
+                } => {
+                    let winit_windows = app.world.non_send_resource::<WinitWindows>();
+                    let Some(entity) = winit_windows.entity_for_window_id(window_id) else {
+                        return;
+                    };
+
+                    let minimized = winit_windows
+                        .get_window(entity)
+                        .and_then(|winit_window| winit_window.is_minimized())
+                        .unwrap_or(occluded);
+                    set_minimized(&mut app.world, entity, minimized);
+                }
+                // Keep the position of the window component the same as the actual position
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::Moved(position),
+                } => {
+                    let winit_windows = app.world.non_send_resource::<WinitWindows>();
+                    let Some(entity) = winit_windows.entity_for_window_id(window_id) else {
+                        return;
+                    };
+
+                    #[cfg(windows)]
+                    winit_windows.apply_cursor_confine_region(entity);
+
+                    let position = IVec2::new(position.x, position.y);
+                    if let Some(mut window) = app.world.get_mut::<Window>(entity) {
+                        window.position = Some(position);
+                    }
+                    app.world.send_event(WindowMovedEvent { entity, position });
+                }
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::Focused(focused),
+                } => {
+                    // Releases of buttons held while the focus moves away never arrive
+                    if !focused {
+                        app.world
+                            .resource_mut::<ButtonInput<KeyCode>>()
+                            .release_all();
+                        app.world
+                            .resource_mut::<ButtonInput<MouseButton>>()
+                            .release_all();
+                    }
+
+                    let mut winit_windows = app.world.non_send_resource_mut::<WinitWindows>();
+                    let Some(entity) = winit_windows.entity_for_window_id(window_id) else {
+                        return;
+                    };
+                    winit_windows.focus_changed(entity, focused);
+                    #[cfg(windows)]
+                    if focused {
+                        winit_windows.apply_cursor_confine_region(entity);
+                    }
+                    apply_focus_change::<WinitWindows>(&mut app.world, entity, focused);
+                }
+                // Keep track of the scale factor the OS uses, like when moving to another monitor
+                Event::WindowEvent {
+                    window_id,
+                    event:
+                        WindowEvent::ScaleFactorChanged {
+                            scale_factor,
+                            mut inner_size_writer,
+                        },
+                } => {
+                    let winit_windows = app.world.non_send_resource::<WinitWindows>();
+                    let Some(entity) = winit_windows.entity_for_window_id(window_id) else {
+                        return;
+                    };
+
+                    if let Some(size) =
+                        apply_scale_factor_change(&mut app.world, entity, scale_factor)
+                    {
+                        let size = PhysicalSize::new(size.x, size.y);
+                        if let Err(err) = inner_size_writer.request_inner_size(size) {
+                            debug!(target: LOG_TARGET, "Could not keep the size of {:?}: {err}", entity);
+                        }
+                    }
+                }
+                // Keep track of the actual theme, which follows the OS if there is no preferred theme
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::ThemeChanged(theme),
+                } => {
+                    let winit_windows = app.world.non_send_resource::<WinitWindows>();
+                    let Some(entity) = winit_windows.entity_for_window_id(window_id) else {
+                        return;
+                    };
+
+                    if let Some(mut window) = app.world.get_mut::<Window>(entity) {
+                        window.theme = Some(theme);
+                    }
+                    app.world.send_event(ThemeChangedEvent { entity, theme });
+                }
+                // Completes the activation token requests of `u_focus_window`
+                #[cfg(target_os = "linux")]
+                Event::WindowEvent {
+                    event: WindowEvent::ActivationTokenDone { serial, token },
+                    ..
+                } => {
+                    let mut winit_windows = app.world.non_send_resource_mut::<WinitWindows>();
+                    let Some(entity) = winit_windows.activation_tokens.deliver(serial, token)
+                    else {
+                        return;
+                    };
+                    winit_windows.drop_stale_activation_tokens();
+
+                    if let Some(winit_window) = winit_windows.get_window(entity) {
+                        if !winit_window.has_focus() {
+                            winit_window.request_user_attention(Some(
+                                winit::window::UserAttentionType::Informational,
+                            ));
+                        }
+                    }
+                }
+                // This is where the frame happens
                 // Don't update if plugins are not ready
-                if app.plugins_state() == PluginsState::Cleaned && !exited {
-                    // Run the frame
-                    app.update();
+                Event::AboutToWait if app.plugins_state() == PluginsState::Cleaned && !exited => {
+                    // While waiting only events passing the wake filter or a timed wakeup run a
+                    // frame, the start cause of the iteration (`WakeFilter::wakes`) tells which
+                    if !wake_requested {
+                        return;
+                    }
+                    wake_requested = false;
 
-                    // Close event loop if received events
-                    if let Some(app_exit_events) = app.world.get_resource::<Events<AppExit>>() {
-                        if app_exit_event_reader.read(app_exit_events).last().is_some() {
+                    stepper.apply_resizes(&mut app);
+
+                    // Run the frame, or only handle closing windows while paused
+                    // Restoring a minimized window wakes the loop up again
+                    let paused = if catch_update_panics {
+                        let Some(paused) =
+                            FrameStepper::<WinitWindows>::run_frame_catching_panics(&mut app)
+                        else {
                             window_target.exit();
                             exited = true;
                             return;
-                        }
+                        };
+                        paused
+                    } else {
+                        FrameStepper::<WinitWindows>::run_frame(&mut app)
+                    };
+
+                    if !paused {
+                        stepper.show_created_windows(&mut app);
+                    }
+
+                    // Close event loop if received events
+                    let exit_requested = app_exit_event_reader
+                        .read(app.world.resource::<Events<AppExit>>())
+                        .last()
+                        .is_some();
+                    if exit_after_frame(&mut app.world, &mut exit_grace_left, exit_requested) {
+                        window_target.exit();
+                        exited = true;
+                        return;
                     }
 
                     // TODO: shouldn't we do the same plugin functions as at the top?
+
+                    // Create any new windows that were added during the frame, and rebuild the
+                    // ones destroyed for `RecreateWindowEvent`
+                    // Windows can only be built here, where the event loop is available
+                    // This only happens once per loop iteration so floods of input events stay cheap
+                    stepper.create_windows(&mut app, window_target);
+
+                    // Systems may have changed how the loop should wait until the next iteration
+                    // Decided after creating windows, so a window spawned this frame gets updates
+                    let control_flow = next_control_flow(&mut app.world);
+                    // `LoopControl::resume` wakes the loop up again
+                    window_target.set_control_flow(if paused {
+                        ControlFlow::Wait
+                    } else {
+                        control_flow
+                    });
                 }
-            }
-            _ => {}
+                // The event loop is about to stop, this is the last event we will receive
+                Event::LoopExiting => {
+                    shut_down::<WinitWindows>(&mut app.world);
+
+                    // Anything left in `WinitWindows` must not outlive the event loop,
+                    // which is dropped when `run` returns
+                    app.world.remove_non_send_resource::<WinitWindows>();
+                }
+                _ => {}
+            };
         };
 
-        // Create any new windows that were added
-        let (query, winit_windows) = create_windows_system_state.get_mut(&mut app.world);
-        create_windows(query, winit_windows, window_target);
-        create_windows_system_state.apply(&mut app.world);
+    // Run event loop
+    info!(target: LOG_TARGET, "Entered event loop");
+    if let Err(err) = event_loop.run(event_handler) {
+        error!(target: LOG_TARGET, "winit event loop error: {err}");
+    }
+}
+
+/// Tears down the windows in a known order instead of whenever the app drops, see `Shutdown`
+///
+/// Windows already announced as closing, like ones waiting for `deferred_destroy`, don't get a
+/// second `WindowClosingEvent`.
+fn shut_down<B: WindowBackend>(world: &mut World) {
+    let closing_windows = std::mem::take(&mut *world.resource_mut::<ClosingWindows>());
+    let announced: Vec<_> = closing_windows
+        .announced
+        .into_iter()
+        .chain(closing_windows.deferred)
+        .collect();
+    let mut entities: Vec<_> = world.resource::<Windows>().iter().collect();
+    for &entity in &entities {
+        if !announced.contains(&entity) {
+            world.send_event(WindowClosingEvent { entity });
+        }
+    }
+    for entity in announced {
+        if !entities.contains(&entity) {
+            entities.push(entity);
+        }
+    }
+
+    // Renderers release their surfaces here, while the windows are still alive
+    world.run_schedule(Shutdown);
+
+    let mut backend = world.non_send_resource_mut::<B>();
+    entities.retain(|&entity| backend.destroy_window(entity));
+    world.resource_mut::<Windows>().0.clear();
+    for entity in entities {
+        world.send_event(WindowDestroyedEvent { entity });
+    }
+}
+
+/// Applies a key event of a window to `Modifiers` and `ButtonInput`, and sends it to the app
+/// `text` is what the key typed, if anything
+fn dispatch_key_input(world: &mut World, event: KeyboardInputEvent, text: Option<String>) {
+    world
+        .resource_mut::<Modifiers>()
+        .key_event(event.physical_key, event.state == ElementState::Pressed);
+    if let PhysicalKey::Code(key_code) = event.physical_key {
+        update_button_input(world, key_code, event.state);
+    }
+    if !world.resource::<InputCapture>().enabled {
+        return;
+    }
+
+    // Typing text repeats no matter the filter
+    if let (ElementState::Pressed, Some(text)) = (event.state, text) {
+        world.send_event(TextInputEvent {
+            entity: event.entity,
+            text,
+        });
+    }
+
+    if event.repeat && world.resource::<RunnerSettings>().filter_key_repeat {
+        return;
+    }
+    world.send_event(event);
+}
+
+/// Presses or releases a button in its `ButtonInput`
+/// Presses are dropped without `InputCapture`, releases always apply so no button stays held down
+fn update_button_input<T: Copy + Eq + Hash + Send + Sync + 'static>(
+    world: &mut World,
+    button: T,
+    state: ElementState,
+) {
+    let capture = world.resource::<InputCapture>().enabled;
+    let mut input = world.resource_mut::<ButtonInput<T>>();
+    match state {
+        ElementState::Pressed if capture => input.press(button),
+        ElementState::Pressed => {}
+        ElementState::Released => input.release(button),
+    }
+}
+
+/// Updates a window that gained or lost focus
+fn apply_focus_change<B: WindowBackend>(world: &mut World, entity: Entity, focused: bool) {
+    let Some(window) = world.get::<Window>(entity) else {
+        return;
     };
+    let mode = window.cursor_grab_mode;
+    // Leave the desktop usable when alt-tabbing out of exclusive fullscreen
+    let minimize = !focused
+        && window.minimize_on_focus_loss
+        && matches!(window.mode, WindowMode::Fullscreen(..));
 
-    // This ensures that new events will be started whenever possible
-    event_loop.set_control_flow(ControlFlow::Poll);
+    let mut backend = world.non_send_resource_mut::<B>();
+    if minimize {
+        backend.set_minimized(entity, true);
+    }
+    // The OS releases the cursor grab when the window loses focus, grab it again once the focus
+    // is back
+    let cursor_grab_active =
+        focused && mode != CursorGrabMode::None && backend.set_cursor_grab(entity, mode);
 
-    // Run event loop
-    info!("Entered event loop");
-    if let Err(err) = event_loop.run(event_handler) {
-        error!("winit event loop error: {err}");
+    if let Some(mut window) = world.get_mut::<Window>(entity) {
+        window.focused = focused;
+        window.cursor_grab_active = cursor_grab_active;
     }
 }
 
-/// Function called to create any winit windows after a new Window component is spawned
-fn create_windows(
-    query: Query<(Entity, &Window), Added<Window>>,
-    mut winit_windows: NonSendMut<WinitWindows>,
-    event_loop: &EventLoopWindowTarget<()>,
+/// Takes the new scale factor the OS uses for a window
+/// Returns the physical size to keep the window at while its scale factor is overridden, since
+/// the OS would otherwise resize it to keep its logical size
+fn apply_scale_factor_change(
+    world: &mut World,
+    entity: Entity,
+    scale_factor: f64,
+) -> Option<UVec2> {
+    let mut window = world.get_mut::<Window>(entity)?;
+    // Already picked up, e.g. by a window mode switch
+    if window.resolution.scale_factor == scale_factor {
+        return None;
+    }
+    window.resolution.scale_factor = scale_factor;
+
+    if window.resolution.scale_factor_override.is_some() {
+        return Some(window.resolution.physical_size());
+    }
+    let scale_factor = window.scale_factor();
+    let effective_scale_factor = window.effective_scale_factor();
+    world.send_event(ScaleFactorChangedEvent {
+        entity,
+        scale_factor,
+        effective_scale_factor,
+    });
+    None
+}
+
+/// Converts a cursor position to logical pixels so it doesn't depend on the monitor
+/// Windows without a `Window` component use the scale factor of their native window
+fn logical_cursor_position(
+    world: &World,
+    entity: Entity,
+    position: PhysicalPosition<f64>,
+    native_scale_factor: impl FnOnce() -> f64,
+) -> Vec2 {
+    let scale_factor = world
+        .get::<Window>(entity)
+        .map_or_else(native_scale_factor, |window| window.scale_factor());
+    let position = position.to_logical::<f32>(scale_factor);
+    Vec2::new(position.x, position.y)
+}
+
+/// Starts the grace period after `AppExit`, returns whether to exit right away instead
+fn start_exit_grace(world: &mut World, exit_grace_left: &mut Option<u32>) -> bool {
+    if exit_grace_left.is_some() {
+        return false;
+    }
+    let frames = world.resource::<RunnerSettings>().exit_grace_frames;
+    if frames == 0 {
+        return true;
+    }
+
+    debug!(target: LOG_TARGET, "Exit requested, running up to {frames} more frames");
+    world.resource_mut::<ExitGrace>().exit_requested = true;
+    *exit_grace_left = Some(frames);
+    false
+}
+
+/// Whether to exit after a frame in which `AppExit` was sent or not
+/// With a grace period the app gets a few more frames to finish up first, which run right away
+fn exit_after_frame(
+    world: &mut World,
+    exit_grace_left: &mut Option<u32>,
+    exit_requested: bool,
+) -> bool {
+    if let Some(left) = exit_grace_left {
+        *left = left.saturating_sub(1);
+    }
+    let exit = match *exit_grace_left {
+        None => exit_requested && start_exit_grace(world, exit_grace_left),
+        Some(left) => left == 0 || world.resource::<ExitGrace>().ready,
+    };
+    if !exit && exit_grace_left.is_some() {
+        world.resource_mut::<ControlFlowSettings>().next_iteration = Some(UpdateMode::Continuous);
+    }
+    exit
+}
+
+/// Remembers the key a physical key produces without modifiers, and sends
+/// `KeyboardLayoutChangedEvent` when it changed
+/// All keys are forgotten on a change, since the whole layout is different now
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn record_keyboard_layout(
+    world: &mut World,
+    layout: &mut HashMap<KeyCode, winit::keyboard::Key>,
+    (physical_key, state): (PhysicalKey, ElementState),
+    key: winit::keyboard::Key,
 ) {
-    for (entity, window) in query.iter() {
-        // If the winit window already exists somehow, don't create another one
-        if winit_windows.entity_to_window.contains_key(&entity) {
+    use winit::keyboard::Key;
+
+    let (PhysicalKey::Code(key_code), ElementState::Pressed) = (physical_key, state) else {
+        return;
+    };
+    // Only keys typing characters depend on the layout
+    if !matches!(key, Key::Character(_)) {
+        return;
+    }
+    match layout.insert(key_code, key.clone()) {
+        Some(previous) if previous != key => {
+            layout.clear();
+            layout.insert(key_code, key);
+            debug!(target: LOG_TARGET, "The keyboard layout changed");
+            world.send_event(KeyboardLayoutChangedEvent);
+        }
+        _ => {}
+    }
+}
+
+/// Whether an event comes from the user using the keyboard, mouse or a touch screen
+fn is_user_input(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput { .. }
+            | WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+    )
+}
+
+/// The control flow for the next iteration, see `ControlFlowSettings::next_control_flow`
+fn next_control_flow(world: &mut World) -> ControlFlow {
+    let has_windows = !world.resource::<Windows>().is_empty();
+    world
+        .resource_mut::<ControlFlowSettings>()
+        .next_control_flow(has_windows)
+}
+
+/// Moves `LastInputTime` to now if the event comes from the user
+fn record_user_input(world: &mut World, event: &WindowEvent) {
+    if is_user_input(event) {
+        world.resource_mut::<LastInputTime>().last_input = Instant::now();
+    }
+}
+
+/// Whether the exit condition is checked
+/// Not while the primary window is still deferred, since there is no window yet, or while the
+/// app runs without windows on purpose
+fn exit_checks_enabled(
+    deferred_primary_window: Option<Res<DeferredPrimaryWindow>>,
+    windowless: Option<Res<Windowless>>,
+) -> bool {
+    deferred_primary_window.is_none() && windowless.is_none()
+}
+
+/// Updates `Window::minimized`, without marking the window as changed if it stays the same
+fn set_minimized(world: &mut World, entity: Entity, minimized: bool) {
+    if let Some(mut window) = world.get_mut::<Window>(entity) {
+        if window.minimized != minimized {
+            window.minimized = minimized;
+        }
+    }
+}
+
+/// Whether there are windows and all of them are minimized
+fn all_windows_minimized(world: &World) -> bool {
+    let windows = world.resource::<Windows>();
+    !windows.is_empty()
+        && windows.iter().all(|entity| {
+            world
+                .get::<Window>(entity)
+                .is_some_and(|window| window.minimized)
+        })
+}
+
+/// Updates the size of a window component after its native window was resized
+fn resize_window<B: WindowBackend>(world: &mut World, entity: Entity, size: PhysicalSize<u32>) {
+    let Some(mut window) = world.get_mut::<Window>(entity) else {
+        return;
+    };
+
+    // The window component stores its size in logical pixels
+    let scale_factor = window.resolution.scale_factor();
+    let physical_size = UVec2::new(size.width, size.height);
+    let size = size.to_logical::<u32>(scale_factor);
+    window.resolution.width = size.width;
+    window.resolution.height = size.height;
+    let window_resolution = window.resolution;
+
+    let current_mode = window.mode;
+
+    // Maximizing or restoring from the title bar, or toggling fullscreen with a shortcut of the
+    // OS, only shows up as a resize
+    let backend = world.non_send_resource::<B>();
+    let maximized = backend.is_maximized(entity);
+    let mode = backend
+        .current_mode(entity, current_mode)
+        .filter(|&mode| mode != current_mode);
+    if let Some(mut window) = world.get_mut::<Window>(entity) {
+        if let Some(maximized) = maximized {
+            window.maximized = maximized;
+        }
+        if let Some(mode) = mode {
+            window.mode = mode;
+        }
+    }
+
+    // The new state is already applied, make sure it isn't sent back to the native window
+    if let Some(mut cache) = world.get_mut::<CachedWindow>(entity) {
+        cache.0.resolution = window_resolution;
+        if let Some(maximized) = maximized {
+            cache.0.maximized = maximized;
+        }
+        if let Some(mode) = mode {
+            cache.0.mode = mode;
+        }
+    }
+    if let Some(mode) = mode {
+        world.send_event(WindowModeChangedEvent { entity, mode });
+    }
+
+    world.send_event(WindowResizedEvent {
+        entity,
+        physical_size,
+        logical_size: UVec2::new(size.width, size.height),
+        scale_factor,
+    });
+}
+
+/// Advances an app one frame at a time, the same way the runner does
+///
+/// This is for tests, tools and embedding the app in another host loop instead of calling
+/// `App::run`. Plugins must be finished (`App::finish` and `App::cleanup`) before stepping.
+/// No window events are delivered in this mode unless the caller pumps the event loop of the
+/// backend itself, so windows don't resize, close or receive input on their own.
+pub struct FrameStepper<B: WindowBackend> {
+    /// Kept between frames so windows are only created once they were added
+    create_windows_system_state: SystemState<CreateWindowsParams<'static, 'static, B>>,
+    /// The last size of each window resized since the last frame, if resizes are coalesced
+    pending_resizes: HashMap<Entity, PhysicalSize<u32>>,
+    /// Windows created hidden that are shown after their first frame, see `wait_for_first_frame`
+    awaiting_first_frame: Vec<Entity>,
+}
+
+#[allow(dead_code)]
+impl<B: WindowBackend> FrameStepper<B> {
+    pub fn new(app: &mut App) -> Self {
+        FrameStepper {
+            create_windows_system_state: SystemState::new(&mut app.world),
+            pending_resizes: HashMap::new(),
+            awaiting_first_frame: Vec::new(),
+        }
+    }
+
+    /// Runs exactly one frame like an iteration of the runner: the queued resizes, one update,
+    /// then the creation of windows spawned during it
+    pub fn step(&mut self, app: &mut App, target: &B::Target) {
+        self.apply_resizes(app);
+        if !Self::run_frame(app) {
+            self.show_created_windows(app);
+        }
+        self.create_windows(app, target);
+    }
+
+    /// Resizes a window before the next frame, only the last size queued for it is applied
+    pub fn queue_resize(&mut self, entity: Entity, size: PhysicalSize<u32>) {
+        self.pending_resizes.insert(entity, size);
+    }
+
+    /// Applies the sizes queued since the last frame, see `WindowPlugin::coalesce_resize_events`
+    pub fn apply_resizes(&mut self, app: &mut App) {
+        for (entity, size) in self.pending_resizes.drain() {
+            resize_window::<B>(&mut app.world, entity, size);
+        }
+    }
+
+    /// Runs one update, or only `Paused` while `LoopControl` pauses the app or every window is
+    /// minimized without `update_when_minimized`
+    /// Returns whether the app was paused
+    pub fn run_frame(app: &mut App) -> bool {
+        let paused = app
+            .world
+            .get_resource::<LoopControl>()
+            .is_some_and(LoopControl::is_paused)
+            || (!app.world.resource::<RunnerSettings>().update_when_minimized
+                && all_windows_minimized(&app.world));
+        if paused {
+            Self::update_paused(app);
+        } else {
+            Self::update(app);
+        }
+        paused
+    }
+
+    /// Like `run_frame`, but a panicking system is logged and `AppExit` sent instead of unwinding
+    /// further, see `WindowPlugin::catch_update_panics`
+    /// Returns `None` if the frame panicked, the app should shut down without running more frames
+    pub fn run_frame_catching_panics(app: &mut App) -> Option<bool> {
+        match panic::catch_unwind(AssertUnwindSafe(|| Self::run_frame(app))) {
+            Ok(paused) => Some(paused),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                error!(target: LOG_TARGET, "A system panicked, exiting: {message}");
+                app.world.send_event(AppExit);
+                None
+            }
+        }
+    }
+
+    /// Runs one update and records its duration in `FrameTimings`
+    pub fn update(app: &mut App) {
+        let frame_start = Instant::now();
+        app.world.resource_mut::<LastInputTime>().frame_start = frame_start;
+        app.update();
+        app.world
+            .resource_mut::<FrameTimings>()
+            .push(frame_start.elapsed());
+
+        // Presses and releases since this frame count for the next one
+        app.world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .clear_just();
+        app.world
+            .resource_mut::<ButtonInput<MouseButton>>()
+            .clear_just();
+
+        let mut startup_timing = app.world.resource_mut::<WindowStartupTiming>();
+        if startup_timing.record_update() {
+            debug!(target: LOG_TARGET,
+                "Started up, first window after {:?}, first update after {:?}",
+                startup_timing.first_window_created, startup_timing.first_update
+            );
+        }
+    }
+
+    /// Runs a frame in which only windows are closed and the exit condition is checked
+    ///
+    /// `First` runs like in an update, so events don't pile up. Presses and releases are
+    /// kept for the next update, which is the first one to see them.
+    pub fn update_paused(app: &mut App) {
+        app.world.run_schedule(First);
+        app.world.run_schedule(Paused);
+        app.world.clear_trackers();
+    }
+
+    /// Shows the windows created hidden because of `wait_for_first_frame`, call this after
+    /// their first frame is done
+    /// Windows whose `visible` was turned off in the meantime stay hidden
+    pub fn show_created_windows(&mut self, app: &mut App) {
+        for entity in self.awaiting_first_frame.drain(..) {
+            let visible = app
+                .world
+                .get::<Window>(entity)
+                .is_some_and(|window| window.visible);
+            if visible {
+                app.world
+                    .non_send_resource_mut::<B>()
+                    .set_visible(entity, true);
+            }
+        }
+    }
+
+    /// Creates the native windows of all windows added since the last call
+    /// Called once when the event loop starts: stores the monitors and sends
+    /// `EventLoopStartedEvent`, then creates the windows spawned so far
+    pub fn start(&mut self, app: &mut App, target: &B::Target, monitors: Monitors) {
+        *app.world.resource_mut::<Monitors>() = monitors;
+        app.world.send_event(EventLoopStartedEvent);
+        self.create_windows(app, target);
+    }
+
+    pub fn create_windows(&mut self, app: &mut App, target: &B::Target) {
+        let params = self.create_windows_system_state.get_mut(&mut app.world);
+        let hidden = create_windows(params, target);
+        self.create_windows_system_state.apply(&mut app.world);
+        self.awaiting_first_frame.extend(hidden);
+    }
+}
+
+/// Function called to create any native windows after a new Window component is spawned
+///
+/// Every window added since the last call is created, so windows spawned together
+/// (like several in the same `Startup` system) are all created before the next update.
+/// Windows that fail to open are tried again in the next calls, see `window_creation_attempts`.
+/// Returns the windows that were created hidden because of `wait_for_first_frame`.
+fn create_windows<B: WindowBackend>(
+    mut params: CreateWindowsParams<B>,
+    target: &B::Target,
+) -> Vec<Entity> {
+    let hide = params.settings.wait_for_first_frame;
+    let mut hidden = Vec::new();
+    // Windows despawned before they could be created don't need to be retried
+    let query = &params.query;
+    params
+        .failed_attempts
+        .retain(|&entity, _| query.contains(entity));
+    // Recreated windows are built again once their old native window was destroyed
+    let backend = &params.backend;
+    let recreate: Vec<_> = params
+        .closing_windows
+        .recreating
+        .iter()
+        .copied()
+        .filter(|&entity| !backend.has_window(entity))
+        .collect();
+    params
+        .closing_windows
+        .recreating
+        .retain(|&entity| query.contains(entity) && !recreate.contains(&entity));
+    for (entity, mut window) in params.query.iter_mut() {
+        // New windows, ones whose creation failed in an earlier frame and recreated ones
+        if !window.is_added()
+            && !params.failed_attempts.contains_key(&entity)
+            && !recreate.contains(&entity)
+        {
+            continue;
+        }
+        // If the native window already exists somehow, don't create another one
+        if params.backend.has_window(entity) {
+            params.failed_attempts.remove(&entity);
+            continue;
+        }
+
+        // Sizes relative to a monitor are resolved against the monitor the window opens on
+        let monitor = match window.mode {
+            WindowMode::Windowed => params.monitors.primary,
+            WindowMode::BorderlessFullscreen(selection) | WindowMode::Fullscreen(selection, _) => {
+                params.monitors.resolve(selection, None)
+            }
+        };
+        let monitor = monitor
+            .and_then(|index| params.monitors.monitors.get(index))
+            .or(params.monitors.monitors.first());
+        window.resolution.resolve_monitor_fraction(monitor);
+
+        // Create the window hidden, the runner shows it once its first frame is done.
+        // The cache keeps the requested visibility so it isn't shown any earlier
+        params.window_settings.apply_defaults(&mut window);
+        if params.settings.ignore_dpi_scaling {
+            window.resolution.scale_factor_override = Some(1.0);
+        }
+
+        let visible = window.visible;
+        if hide && visible {
+            window.visible = false;
+            hidden.push(entity);
+        }
+        let result = params.backend.create_window(target, entity, &mut window);
+        window.visible = visible;
+        if let Err(err) = result {
+            hidden.retain(|&hidden| hidden != entity);
+            let attempts = params.failed_attempts.entry(entity).or_default();
+            *attempts += 1;
+            if *attempts < params.settings.window_creation_attempts {
+                warn!(target: LOG_TARGET,
+                    "Could not open window {} on {:?} (attempt {}/{}), retrying next frame: {err}",
+                    window.title, entity, attempts, params.settings.window_creation_attempts
+                );
+                // Make sure there is a next frame even if the loop would wait for events
+                params.control_flow.next_iteration = Some(UpdateMode::Continuous);
+            } else {
+                error!(target: LOG_TARGET, "Could not open window {} on {:?}: {err}", window.title, entity);
+                params.failed_attempts.remove(&entity);
+                params
+                    .creation_failed_events
+                    .send(WindowCreationFailedEvent { entity, error: err });
+            }
             continue;
         }
+        params.failed_attempts.remove(&entity);
+        params.startup_timing.record_window_created();
 
-        winit_windows.create_window(event_loop, entity, window);
+        params
+            .commands
+            .entity(entity)
+            .insert(CachedWindow(window.clone()));
+        params.windows.0.push(entity);
+        params.created_events.send(WindowCreatedEvent { entity });
     }
+    hidden
+}
+
+/// Everything `create_windows` needs from the world
+#[derive(SystemParam)]
+struct CreateWindowsParams<'w, 's, B: WindowBackend> {
+    commands: Commands<'w, 's>,
+    query: Query<'w, 's, (Entity, &'static mut Window)>,
+    backend: NonSendMut<'w, B>,
+    windows: ResMut<'w, Windows>,
+    closing_windows: ResMut<'w, ClosingWindows>,
+    settings: Res<'w, RunnerSettings>,
+    monitors: Res<'w, Monitors>,
+    window_settings: Res<'w, WindowSettings>,
+    control_flow: ResMut<'w, ControlFlowSettings>,
+    startup_timing: ResMut<'w, WindowStartupTiming>,
+    created_events: EventWriter<'w, WindowCreatedEvent>,
+    creation_failed_events: EventWriter<'w, WindowCreationFailedEvent>,
+    /// How often creating each pending window failed so far
+    failed_attempts: Local<'s, HashMap<Entity, u32>>,
 }
 
 /// The condition at which the event loop will quit
+///
+/// The condition is checked in `PostUpdate`. Windows closed during `Update` are already
+/// despawned and their native windows destroyed by then, see `WindowCloseSet`.
 #[allow(dead_code)]
 #[derive(Default)]
 pub enum ExitCondition {
@@ -199,6 +1462,1431 @@ pub enum ExitCondition {
     /// Quit when all windows are closed
     #[default]
     OnAllClosed,
+    /// Quit as soon as the predicate returns `true`, see `ExitCondition::custom`
+    Custom(ExitPredicate),
     /// Don't quit no matter what
     DontExit,
 }
+
+/// A predicate run every frame to decide whether to quit
+pub type ExitPredicate = Arc<dyn Fn(&World) -> bool + Send + Sync>;
+
+#[allow(dead_code)]
+impl ExitCondition {
+    /// The name of the variant, the predicate of `Custom` can't be shown
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExitCondition::OnPrimaryClosed => "OnPrimaryClosed",
+            ExitCondition::OnAllClosed => "OnAllClosed",
+            ExitCondition::Custom(_) => "Custom",
+            ExitCondition::DontExit => "DontExit",
+        }
+    }
+
+    /// Quit as soon as `predicate` returns `true`, like when a few specific windows are all closed
+    pub fn custom(predicate: impl Fn(&World) -> bool + Send + Sync + 'static) -> Self {
+        ExitCondition::Custom(Arc::new(predicate))
+    }
+}
+
+/// How often the event loop runs the app
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateMode {
+    /// Update as often as possible, even if nothing happened
+    #[default]
+    Continuous,
+    /// Update only after receiving events, or once `wait` passed without any
+    /// Use `Duration::MAX` to wait for events indefinitely
+    Reactive { wait: Duration },
+}
+
+/// Kinds of frequent events that can be kept from waking up a waiting event loop
+/// Every other event always wakes it up
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WakeFilter {
+    /// Whether moving the cursor over a window wakes the loop
+    pub cursor_moved: bool,
+    /// Whether raw device events like mouse motion wake the loop
+    pub device_events: bool,
+}
+
+impl Default for WakeFilter {
+    fn default() -> Self {
+        WakeFilter {
+            cursor_moved: true,
+            device_events: true,
+        }
+    }
+}
+
+impl WakeFilter {
+    /// Only wakes up for events other than cursor and device motion, or the timeout
+    #[allow(dead_code)]
+    pub const LOW_POWER: WakeFilter = WakeFilter {
+        cursor_moved: false,
+        device_events: false,
+    };
+
+    /// Whether an event should run a frame when the loop is waiting
+    fn wakes(&self, event: &Event<RunnerEvent>) -> bool {
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { .. },
+                ..
+            } => self.cursor_moved,
+            Event::DeviceEvent { .. } => self.device_events,
+            // The loop started, or the app asked to run again right away
+            Event::NewEvents(StartCause::Init | StartCause::Poll) => true,
+            // The `wait` timeout of `UpdateMode::Reactive` passed
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => true,
+            // Events arrived while waiting, they decide by themselves unless the timeout passed
+            // before they were handled
+            Event::NewEvents(StartCause::WaitCancelled {
+                requested_resume, ..
+            }) => requested_resume.is_some_and(|resume| Instant::now() >= resume),
+            Event::AboutToWait => false,
+            _ => true,
+        }
+    }
+}
+
+impl UpdateMode {
+    /// The control flow that makes the event loop update in this mode
+    pub fn control_flow(&self) -> ControlFlow {
+        match self {
+            UpdateMode::Continuous => ControlFlow::Poll,
+            UpdateMode::Reactive { wait } => match Instant::now().checked_add(*wait) {
+                Some(instant) => ControlFlow::WaitUntil(instant),
+                None => ControlFlow::Wait,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::window::backend::MockBackend;
+    use crate::core::window::components::MonitorSelection;
+    use crate::core::window::testing::{self, TestApp};
+
+    #[test]
+    fn window_lifecycle() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        assert_eq!(test.backend().created, [entity]);
+        assert_eq!(test.events::<WindowCreatedEvent>().len(), 1);
+        assert!(test.world().resource::<Windows>().contains(entity));
+        assert!(test.world().get::<CachedWindow>(entity).is_some());
+
+        test.send(WindowCloseRequestedEvent { entity });
+        test.step();
+        assert!(test.world().get_entity(entity).is_none());
+        let closing = test.events::<WindowClosingEvent>();
+        assert_eq!(closing.len(), 1);
+        assert_eq!(closing[0].entity, entity);
+        let destroyed = test.events::<WindowDestroyedEvent>();
+        assert_eq!(destroyed.len(), 1);
+        assert_eq!(destroyed[0].entity, entity);
+        assert_eq!(test.backend().destroyed, [entity]);
+        assert!(test.world().resource::<Windows>().is_empty());
+    }
+
+    #[test]
+    fn step_runs_one_update() {
+        let mut test = TestApp::windowless();
+        test.step();
+        test.step();
+        assert_eq!(test.world().resource::<FrameTimings>().samples().count(), 2);
+    }
+
+    #[test]
+    fn step_clears_just_pressed() {
+        let mut test = TestApp::windowless();
+        test.world()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::Space);
+        test.step();
+        let keyboard = test.world().resource::<ButtonInput<KeyCode>>();
+        assert!(keyboard.pressed(KeyCode::Space));
+        assert!(!keyboard.just_pressed(KeyCode::Space));
+    }
+
+    #[test]
+    fn step_applies_last_queued_resize() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        test.stepper
+            .queue_resize(entity, PhysicalSize::new(300, 200));
+        test.stepper
+            .queue_resize(entity, PhysicalSize::new(400, 300));
+        test.step();
+
+        // Sent before the update, so it is in the buffer of the last frame
+        let events = test.world().resource::<Events<WindowResizedEvent>>();
+        let sizes: Vec<_> = events
+            .get_reader()
+            .read(events)
+            .map(|event| event.physical_size)
+            .collect();
+        assert_eq!(sizes, [UVec2::new(400, 300)]);
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(window.resolution.physical_size(), UVec2::new(400, 300));
+    }
+
+    #[derive(Resource, Default)]
+    struct ShutdownRuns(u32);
+
+    fn count_shutdown_runs(mut runs: ResMut<ShutdownRuns>) {
+        runs.0 += 1;
+    }
+
+    #[test]
+    fn shutdown_runs_once_after_exit() {
+        let mut test = TestApp::windowless();
+        test.app.init_resource::<ShutdownRuns>();
+        test.app.add_systems(Shutdown, count_shutdown_runs);
+
+        // `AppExit` only stops the loop, `Shutdown` runs once it is exiting
+        test.send(AppExit);
+        test.step();
+        assert_eq!(test.world().resource::<ShutdownRuns>().0, 0);
+        shut_down::<MockBackend>(test.world());
+        assert_eq!(test.world().resource::<ShutdownRuns>().0, 1);
+    }
+
+    #[test]
+    fn window_creation_is_retried_after_a_failure() {
+        let mut test = TestApp::windowless();
+        test.backend_mut().failing_creations = 1;
+        let entity = test.spawn_window(Window::default());
+        assert!(test.backend().created.is_empty());
+        assert!(test.events::<WindowCreationFailedEvent>().is_empty());
+        assert_eq!(
+            test.world()
+                .resource::<ControlFlowSettings>()
+                .next_iteration,
+            Some(UpdateMode::Continuous)
+        );
+
+        test.step();
+        assert_eq!(test.backend().created, [entity]);
+        assert!(test.events::<WindowCreationFailedEvent>().is_empty());
+
+        // Every attempt fails
+        test.backend_mut().failing_creations = 3;
+        let failing = test.spawn_window(Window::default());
+        test.step();
+        assert!(test.events::<WindowCreationFailedEvent>().is_empty());
+        test.step();
+        let failures = test.events::<WindowCreationFailedEvent>();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].entity, failing);
+        assert_eq!(test.backend().created, [entity]);
+    }
+
+    #[test]
+    fn exit_waits_for_the_app_to_be_ready() {
+        let mut test = TestApp::new(WindowPlugin {
+            exit_grace_frames: 10,
+            ..Default::default()
+        });
+        test.step();
+        let mut exit_grace_left = None;
+        assert!(!exit_after_frame(test.world(), &mut exit_grace_left, false));
+        assert!(!test.world().resource::<ExitGrace>().exit_requested());
+
+        assert!(!exit_after_frame(test.world(), &mut exit_grace_left, true));
+        assert!(test.world().resource::<ExitGrace>().exit_requested());
+        for _ in 0..3 {
+            test.step();
+            assert!(!exit_after_frame(test.world(), &mut exit_grace_left, false));
+        }
+
+        test.world().resource_mut::<ExitGrace>().ready = true;
+        test.step();
+        assert!(exit_after_frame(test.world(), &mut exit_grace_left, false));
+    }
+
+    #[test]
+    fn exit_grace_period_is_capped() {
+        let mut test = TestApp::new(WindowPlugin {
+            exit_grace_frames: 3,
+            ..Default::default()
+        });
+        let mut exit_grace_left = None;
+        assert!(!exit_after_frame(test.world(), &mut exit_grace_left, true));
+        let frames = (0..10)
+            .take_while(|_| !exit_after_frame(test.world(), &mut exit_grace_left, false))
+            .count();
+        // The third frame after `AppExit` is the last one
+        assert_eq!(frames, 2);
+    }
+
+    fn panic_once(mut panicked: Local<bool>) {
+        if !*panicked {
+            *panicked = true;
+            panic!("first frame failed");
+        }
+    }
+
+    #[test]
+    fn panicking_frame_exits_cleanly() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        test.app.init_resource::<ShutdownRuns>();
+        test.app.add_systems(Update, panic_once);
+        test.app.add_systems(Shutdown, count_shutdown_runs);
+
+        assert_eq!(
+            FrameStepper::<MockBackend>::run_frame_catching_panics(&mut test.app),
+            None
+        );
+        assert_eq!(test.events::<AppExit>().len(), 1);
+
+        shut_down::<MockBackend>(test.world());
+        assert_eq!(test.world().resource::<ShutdownRuns>().0, 1);
+        assert_eq!(test.backend().destroyed, [entity]);
+    }
+
+    #[test]
+    fn app_keeps_running_without_windows_until_windowless_is_removed() {
+        let mut test = TestApp::new(WindowPlugin::default());
+        test.step();
+        let secondary = test.spawn_window(Window::default());
+        let primary = test
+            .world()
+            .resource::<PrimaryWindowEntity>()
+            .get()
+            .unwrap();
+
+        test.send(EnterWindowlessEvent);
+        test.step();
+        assert!(test.world().get_entity(primary).is_none());
+        assert!(test.world().get_entity(secondary).is_none());
+        assert_eq!(test.events::<WindowDestroyedEvent>().len(), 2);
+        assert!(test.events::<AppExit>().is_empty());
+
+        for _ in 0..3 {
+            let frames = test.world().resource::<FrameTimings>().samples().count();
+            test.step();
+            assert_eq!(
+                test.world().resource::<FrameTimings>().samples().count(),
+                frames + 1
+            );
+            assert!(test.events::<AppExit>().is_empty());
+            assert_eq!(test.world().resource::<PrimaryWindowEntity>().get(), None);
+            let counts = test.world().resource::<WindowCounts>();
+            assert_eq!((counts.total(), counts.primary()), (0, 0));
+        }
+
+        test.world().remove_resource::<Windowless>();
+        test.step();
+        assert_eq!(test.events::<AppExit>().len(), 1);
+    }
+
+    #[test]
+    fn paused_app_updates_events() {
+        let mut test = TestApp::windowless();
+        test.world().resource::<LoopControl>().pause();
+        test.send(DumpWindowDiagnosticsEvent);
+        test.step();
+        test.step();
+        assert!(test
+            .world()
+            .resource::<Events<DumpWindowDiagnosticsEvent>>()
+            .is_empty());
+    }
+
+    #[test]
+    fn minimized_app_updates_unless_told_not_to() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        set_minimized(test.world(), entity, true);
+        let frames = |test: &mut TestApp| test.world().resource::<FrameTimings>().samples().count();
+
+        let before = frames(&mut test);
+        test.step();
+        assert_eq!(frames(&mut test), before + 1);
+
+        test.world()
+            .resource_mut::<RunnerSettings>()
+            .update_when_minimized = false;
+        let before = frames(&mut test);
+        test.step();
+        test.step();
+        assert_eq!(frames(&mut test), before);
+
+        set_minimized(test.world(), entity, false);
+        test.step();
+        assert_eq!(frames(&mut test), before + 1);
+    }
+
+    #[test]
+    fn paused_app_closes_windows() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        test.world().resource::<LoopControl>().pause();
+        test.send(WindowCloseRequestedEvent { entity });
+        test.step();
+        assert!(test.world().get_entity(entity).is_none());
+        assert_eq!(test.events::<WindowDestroyedEvent>().len(), 1);
+        assert_eq!(test.backend().destroyed, [entity]);
+    }
+
+    #[test]
+    fn paused_app_keeps_just_pressed() {
+        let mut test = TestApp::windowless();
+        test.step();
+        test.world().resource::<LoopControl>().pause();
+        test.world()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::Space);
+        test.step();
+        assert!(test
+            .world()
+            .resource::<ButtonInput<KeyCode>>()
+            .just_pressed(KeyCode::Space));
+
+        test.world().resource::<LoopControl>().resume();
+        test.step();
+        assert!(!test
+            .world()
+            .resource::<ButtonInput<KeyCode>>()
+            .just_pressed(KeyCode::Space));
+    }
+
+    #[test]
+    fn minimized_app_closes_windows() {
+        let mut test = TestApp::new(WindowPlugin {
+            primary_window: None,
+            update_when_minimized: false,
+            ..Default::default()
+        });
+        let entity = test.spawn_window(Window::default());
+        set_minimized(test.world(), entity, true);
+        let frames = test.world().resource::<FrameTimings>().samples().count();
+
+        test.send(WindowCloseRequestedEvent { entity });
+        test.step();
+        assert_eq!(
+            test.world().resource::<FrameTimings>().samples().count(),
+            frames
+        );
+        assert!(test.world().get_entity(entity).is_none());
+        assert_eq!(test.events::<WindowClosingEvent>().len(), 1);
+        assert_eq!(test.backend().destroyed, [entity]);
+    }
+
+    fn retitle_deferred_primary_window(mut deferred: ResMut<DeferredPrimaryWindow>) {
+        if let Some(window) = &mut deferred.0 {
+            window.title = "Loaded from settings".to_string();
+        }
+    }
+
+    #[test]
+    fn deferred_primary_window_is_configured_by_startup() {
+        let mut test = TestApp::new(WindowPlugin {
+            defer_primary_window: true,
+            ..Default::default()
+        });
+        test.app
+            .add_systems(Startup, retitle_deferred_primary_window);
+        assert!(test.world().resource::<DeferredPrimaryWindow>().0.is_some());
+        assert!(test
+            .world()
+            .query::<&Window>()
+            .iter(test.world())
+            .next()
+            .is_none());
+
+        test.step();
+        assert!(test
+            .world()
+            .get_resource::<DeferredPrimaryWindow>()
+            .is_none());
+        let (entity, window) = test
+            .world()
+            .query_filtered::<(Entity, &Window), With<PrimaryWindow>>()
+            .single(test.world());
+        assert_eq!(window.title, "Loaded from settings");
+        assert_eq!(test.backend().created, [entity]);
+        assert!(test.events::<AppExit>().is_empty());
+    }
+
+    fn clear_deferred_primary_window(mut deferred: ResMut<DeferredPrimaryWindow>) {
+        deferred.0 = None;
+    }
+
+    #[test]
+    fn cleared_deferred_primary_window_is_not_spawned() {
+        let mut test = TestApp::new(WindowPlugin {
+            defer_primary_window: true,
+            exit_condition: ExitCondition::DontExit,
+            ..Default::default()
+        });
+        test.app.add_systems(Startup, clear_deferred_primary_window);
+        test.step();
+        assert!(test
+            .world()
+            .get_resource::<DeferredPrimaryWindow>()
+            .is_none());
+        assert!(test.backend().created.is_empty());
+    }
+
+    fn spawn_three_windows(mut commands: Commands) {
+        for title in ["Scene", "Inspector", "Assets"] {
+            commands.spawn(Window {
+                title: title.to_string(),
+                ..Default::default()
+            });
+        }
+    }
+
+    #[test]
+    fn windows_spawned_together_are_all_created() {
+        let mut test = TestApp::windowless();
+        test.app.add_systems(Startup, spawn_three_windows);
+        test.step();
+        assert_eq!(test.backend().created.len(), 3);
+        assert_eq!(test.events::<WindowCreatedEvent>().len(), 3);
+        assert_eq!(test.world().resource::<Windows>().len(), 3);
+
+        // Created once, even though the windows stay in the query
+        test.step();
+        assert_eq!(test.backend().created.len(), 3);
+        assert!(test.events::<WindowCreatedEvent>().is_empty());
+    }
+
+    #[test]
+    fn shut_down_destroys_every_window() {
+        let mut test = TestApp::windowless();
+        let entities: Vec<_> = (0..3)
+            .map(|_| test.spawn_window(Window::default()))
+            .collect();
+
+        shut_down::<MockBackend>(test.world());
+        let destroyed: Vec<_> = test
+            .events::<WindowDestroyedEvent>()
+            .iter()
+            .map(|event| event.entity)
+            .collect();
+        assert_eq!(destroyed, entities);
+        assert_eq!(test.backend().destroyed, entities);
+        assert!(test.backend().windows.is_empty());
+        assert!(test.world().resource::<Windows>().is_empty());
+    }
+
+    #[test]
+    fn created_window_has_the_scale_factor_of_its_monitor() {
+        let mut test = TestApp::windowless();
+        test.backend_mut().scale_factor = Some(2.0);
+        let entity = test.spawn_window(Window::default());
+
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(window.scale_factor(), 2.0);
+        assert_eq!(window.resolution.physical_size(), UVec2::new(1600, 1200));
+        let cache = test.world().get::<CachedWindow>(entity).unwrap();
+        assert_eq!(cache.0.scale_factor(), 2.0);
+
+        // Nothing is left to pick up in the first frame
+        test.step();
+        assert!(test.events::<ScaleFactorChangedEvent>().is_empty());
+        assert!(test.backend().resize_requests.is_empty());
+    }
+
+    /// The windows a renderer saw closing, and whether their native window still existed
+    #[derive(Resource, Default)]
+    struct DroppedSurfaces(Vec<(Entity, bool)>);
+
+    fn drop_surfaces(
+        mut closing_events: EventReader<WindowClosingEvent>,
+        backend: NonSend<MockBackend>,
+        mut dropped: ResMut<DroppedSurfaces>,
+    ) {
+        for event in closing_events.read() {
+            dropped
+                .0
+                .push((event.entity, backend.has_window(event.entity)));
+        }
+    }
+
+    #[test]
+    fn closing_is_announced_before_the_window_is_destroyed() {
+        let mut test = TestApp::windowless();
+        test.app.init_resource::<DroppedSurfaces>();
+        test.app.add_systems(
+            WindowClose,
+            drop_surfaces
+                .after(WindowCloseSet::Announce)
+                .before(WindowCloseSet::Destroy),
+        );
+        let entity = test.spawn_window(Window::default());
+
+        test.world().despawn(entity);
+        test.step();
+        assert_eq!(
+            test.world().resource::<DroppedSurfaces>().0,
+            [(entity, true)]
+        );
+        assert_eq!(test.backend().destroyed, [entity]);
+        assert_eq!(test.events::<WindowDestroyedEvent>().len(), 1);
+    }
+
+    /// Opens a transient window and closes it again later in the same frame
+    fn flash_window(mut commands: Commands, mut flashed: Local<bool>) {
+        if !*flashed {
+            *flashed = true;
+            let entity = commands.spawn(Window::default()).id();
+            commands.add(move |world: &mut World| {
+                world.despawn(entity);
+            });
+        }
+    }
+
+    #[test]
+    fn window_spawned_and_despawned_in_one_frame_is_never_created() {
+        let mut test = TestApp::windowless();
+        test.app.add_systems(Update, flash_window);
+        test.step();
+        test.step();
+
+        assert!(test.backend().created.is_empty());
+        assert!(test.backend().destroyed.is_empty());
+        assert!(test.world().resource::<Windows>().is_empty());
+        assert!(test.events::<WindowCreatedEvent>().is_empty());
+        assert!(test.events::<WindowClosingEvent>().is_empty());
+    }
+
+    #[test]
+    fn windows_follow_spawns_and_despawns() {
+        let mut test = TestApp::windowless();
+        let first = test.spawn_window(Window::default());
+        let second = test.spawn_window(Window::default());
+        assert_eq!(
+            test.world()
+                .resource::<Windows>()
+                .iter()
+                .collect::<Vec<_>>(),
+            [first, second]
+        );
+
+        test.world().despawn(first);
+        test.step();
+        assert_eq!(
+            test.world()
+                .resource::<Windows>()
+                .iter()
+                .collect::<Vec<_>>(),
+            [second]
+        );
+
+        // Spawned and despawned before it was created
+        let never_created = test.world().spawn(Window::default()).id();
+        test.world().despawn(never_created);
+        test.step();
+        assert_eq!(test.world().resource::<Windows>().len(), 1);
+        assert!(!test.world().resource::<Windows>().contains(never_created));
+    }
+
+    #[test]
+    fn releases_apply_without_input_capture() {
+        let mut test = TestApp::windowless();
+        update_button_input(test.world(), KeyCode::KeyW, ElementState::Pressed);
+        update_button_input(test.world(), MouseButton::Left, ElementState::Pressed);
+        test.world().resource_mut::<InputCapture>().enabled = false;
+
+        update_button_input(test.world(), KeyCode::KeyW, ElementState::Released);
+        update_button_input(test.world(), MouseButton::Left, ElementState::Released);
+        update_button_input(test.world(), KeyCode::KeyA, ElementState::Pressed);
+        let keyboard = test.world().resource::<ButtonInput<KeyCode>>();
+        assert!(!keyboard.pressed(KeyCode::KeyW));
+        assert!(keyboard.just_released(KeyCode::KeyW));
+        assert!(!keyboard.pressed(KeyCode::KeyA));
+        let mouse = test.world().resource::<ButtonInput<MouseButton>>();
+        assert!(!mouse.pressed(MouseButton::Left));
+    }
+
+    #[test]
+    fn shut_down_announces_deferred_windows_once() {
+        let mut test = TestApp::new(WindowPlugin {
+            primary_window: None,
+            deferred_destroy: true,
+            exit_condition: ExitCondition::DontExit,
+            ..Default::default()
+        });
+        let closed = test.spawn_window(Window::default());
+        let open = test.spawn_window(Window::default());
+
+        test.send(WindowCloseRequestedEvent { entity: closed });
+        test.step();
+        assert!(test.backend().destroyed.is_empty());
+        shut_down::<MockBackend>(test.world());
+
+        let closing: Vec<_> = test
+            .events::<WindowClosingEvent>()
+            .iter()
+            .map(|event| event.entity)
+            .collect();
+        assert_eq!(closing, [closed, open]);
+        let mut destroyed = test.backend().destroyed.clone();
+        destroyed.sort();
+        let mut expected = vec![closed, open];
+        expected.sort();
+        assert_eq!(destroyed, expected);
+        assert_eq!(test.events::<WindowDestroyedEvent>().len(), 2);
+        assert!(test.world().resource::<Windows>().is_empty());
+    }
+
+    fn deny_all_closes(
+        mut requests: EventReader<WindowCloseRequestedEvent>,
+        mut denied: ResMut<WindowCloseDenied>,
+    ) {
+        for request in requests.read() {
+            denied.deny(request.entity);
+        }
+    }
+
+    #[test]
+    fn denied_close_keeps_the_window_for_that_frame() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        test.app
+            .add_systems(WindowClose, deny_all_closes.in_set(WindowCloseSet::Veto));
+
+        test.send(WindowCloseRequestedEvent { entity });
+        test.step();
+        assert!(test.world().get::<Window>(entity).is_some());
+        assert!(test.events::<WindowClosingEvent>().is_empty());
+        assert!(test.world().resource::<WindowCloseDenied>().0.is_empty());
+    }
+
+    #[test]
+    fn paused_app_runs_veto_systems() {
+        let mut test = TestApp::windowless();
+        test.app
+            .add_systems(WindowClose, deny_all_closes.in_set(WindowCloseSet::Veto));
+        let entity = test.spawn_window(Window::default());
+        test.world().resource::<LoopControl>().pause();
+
+        test.send(WindowCloseRequestedEvent { entity });
+        test.step();
+        assert!(test.world().get::<Window>(entity).is_some());
+        assert!(test.backend().destroyed.is_empty());
+    }
+
+    #[test]
+    fn closing_the_primary_window_exits() {
+        let mut test = TestApp::new(WindowPlugin {
+            exit_condition: ExitCondition::OnPrimaryClosed,
+            ..Default::default()
+        });
+        test.step();
+        let primary = test
+            .world()
+            .resource::<PrimaryWindowEntity>()
+            .get()
+            .unwrap();
+        let secondary = test.spawn_window(Window::default());
+
+        test.send(WindowCloseRequestedEvent { entity: secondary });
+        test.step();
+        assert!(test.events::<AppExit>().is_empty());
+
+        test.send(WindowCloseRequestedEvent { entity: primary });
+        test.step();
+        assert_eq!(test.events::<WindowDestroyedEvent>().len(), 1);
+        assert_eq!(test.events::<AppExit>().len(), 1);
+    }
+
+    #[test]
+    fn shut_down_releases_surfaces_before_destroying_windows() {
+        let mut test = TestApp::windowless();
+        test.app.init_resource::<DroppedSurfaces>();
+        test.app.add_systems(Shutdown, drop_surfaces);
+        let entities = [
+            test.spawn_window(Window::default()),
+            test.spawn_window(Window::default()),
+        ];
+
+        shut_down::<MockBackend>(test.world());
+        assert_eq!(
+            test.world().resource::<DroppedSurfaces>().0,
+            entities.map(|entity| (entity, true))
+        );
+        assert!(test.backend().windows.is_empty());
+        assert_eq!(test.events::<WindowDestroyedEvent>().len(), 2);
+    }
+
+    #[test]
+    fn windows_are_shown_after_their_first_frame() {
+        let mut test = TestApp::new(WindowPlugin {
+            primary_window: None,
+            wait_for_first_frame: true,
+            ..Default::default()
+        });
+        let entity = test.spawn_window(Window::default());
+        assert!(!test.backend().windows[&entity].visible);
+        assert!(test.world().get::<Window>(entity).unwrap().visible);
+        test.step();
+        assert!(test.backend().windows[&entity].visible);
+
+        // A paused frame doesn't count
+        let entity = test.spawn_window(Window::default());
+        test.world().resource::<LoopControl>().pause();
+        test.step();
+        assert!(!test.backend().windows[&entity].visible);
+        test.world().resource::<LoopControl>().resume();
+        test.step();
+        assert!(test.backend().windows[&entity].visible);
+
+        // Hidden before the first frame was done
+        let entity = test.spawn_window(Window::default());
+        test.world().get_mut::<Window>(entity).unwrap().visible = false;
+        test.step();
+        assert!(!test.backend().windows[&entity].visible);
+    }
+
+    #[test]
+    fn monitor_relative_size_is_resolved_on_creation() {
+        let mut test = TestApp::windowless();
+        *test.world().resource_mut::<Monitors>() = testing::two_monitors();
+        let windowed = test.spawn_window(Window {
+            resolution: WindowResolution::percent(0.8, 0.5),
+            ..Default::default()
+        });
+        let fullscreen = test.spawn_window(Window {
+            resolution: WindowResolution::percent(0.5, 0.5),
+            mode: WindowMode::BorderlessFullscreen(MonitorSelection::Index(1)),
+            ..Default::default()
+        });
+
+        // The primary monitor is 1920x1080, the other one has 1920x1080 logical pixels at 2x
+        let size = |test: &mut TestApp, entity| {
+            let resolution = test.world().get::<Window>(entity).unwrap().resolution;
+            (resolution.width, resolution.height)
+        };
+        assert_eq!(size(&mut test, windowed), (1536, 540));
+        assert_eq!(size(&mut test, fullscreen), (960, 540));
+        let created = &test.backend().windows[&windowed].resolution;
+        assert_eq!((created.width, created.height), (1536, 540));
+    }
+
+    #[test]
+    fn event_loop_start_is_sent_once_before_the_first_window() {
+        let mut test = TestApp::new(WindowPlugin {
+            monitor_relative_default_size: true,
+            ..Default::default()
+        });
+        test.stepper
+            .start(&mut test.app, &(), testing::two_monitors());
+        assert_eq!(test.events::<EventLoopStartedEvent>().len(), 1);
+        assert_eq!(test.events::<WindowCreatedEvent>().len(), 1);
+        // The monitors were already there when the primary window was created
+        let primary = test.backend().created[0];
+        let resolution = test.world().get::<Window>(primary).unwrap().resolution;
+        assert_eq!((resolution.width, resolution.height), (1280, 720));
+
+        test.step();
+        test.spawn_window(Window::default());
+        assert!(test.events::<EventLoopStartedEvent>().is_empty());
+        assert_eq!(test.events::<WindowCreatedEvent>().len(), 1);
+    }
+
+    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+    #[test]
+    fn keyboard_layout_change_is_noticed_on_the_next_key_press() {
+        use winit::keyboard::{Key, NamedKey};
+
+        let mut test = TestApp::windowless();
+        let mut layout = HashMap::new();
+        let mut press = |test: &mut TestApp, key_code, key: Key| {
+            let physical_key = PhysicalKey::Code(key_code);
+            for state in [ElementState::Pressed, ElementState::Released] {
+                record_keyboard_layout(
+                    test.world(),
+                    &mut layout,
+                    (physical_key, state),
+                    key.clone(),
+                );
+            }
+            test.events::<KeyboardLayoutChangedEvent>().len()
+        };
+
+        // QWERTY
+        assert_eq!(
+            press(&mut test, KeyCode::KeyQ, Key::Character("q".into())),
+            0
+        );
+        assert_eq!(
+            press(&mut test, KeyCode::KeyA, Key::Character("a".into())),
+            0
+        );
+        assert_eq!(
+            press(&mut test, KeyCode::KeyQ, Key::Character("q".into())),
+            0
+        );
+        assert_eq!(
+            press(&mut test, KeyCode::ShiftLeft, Key::Named(NamedKey::Shift)),
+            0
+        );
+        // The user switched to AZERTY
+        assert_eq!(
+            press(&mut test, KeyCode::KeyQ, Key::Character("a".into())),
+            1
+        );
+        assert_eq!(
+            press(&mut test, KeyCode::KeyA, Key::Character("q".into())),
+            1
+        );
+        assert_eq!(
+            press(&mut test, KeyCode::KeyQ, Key::Character("a".into())),
+            1
+        );
+    }
+
+    #[test]
+    fn default_size_follows_the_scale_of_the_primary_monitor() {
+        let default_size = |monitor| {
+            let mut test = TestApp::new(WindowPlugin {
+                monitor_relative_default_size: true,
+                ..Default::default()
+            });
+            *test.world().resource_mut::<Monitors>() = Monitors {
+                monitors: vec![monitor],
+                primary: Some(0),
+            };
+            test.step();
+            let primary = test
+                .world()
+                .resource::<PrimaryWindowEntity>()
+                .get()
+                .unwrap();
+            let resolution = test.world().get::<Window>(primary).unwrap().resolution;
+            (resolution.width, resolution.height)
+        };
+
+        // Two thirds of the logical size of the monitor
+        assert_eq!(
+            default_size(testing::monitor(0, UVec2::new(2560, 1440), 1.0)),
+            (1707, 960)
+        );
+        assert_eq!(
+            default_size(testing::monitor(0, UVec2::new(3840, 2160), 2.0)),
+            (1280, 720)
+        );
+    }
+
+    #[test]
+    fn unset_window_fields_use_the_window_settings() {
+        let mut test = TestApp::windowless();
+        test.app.insert_resource(WindowSettings {
+            decorations: false,
+            vsync: false,
+        });
+        let inherits = test.spawn_window(Window::default());
+        let overrides = test.spawn_window(Window {
+            decorations: Some(true),
+            ..Default::default()
+        });
+
+        let settings = |test: &mut TestApp, entity| {
+            let window = test.world().get::<Window>(entity).unwrap();
+            (window.decorations, window.vsync)
+        };
+        assert_eq!(settings(&mut test, inherits), (Some(false), Some(false)));
+        assert_eq!(settings(&mut test, overrides), (Some(true), Some(false)));
+        assert_eq!(test.backend().windows[&inherits].decorations, Some(false));
+    }
+
+    #[test]
+    fn window_moves_to_the_same_spot_on_another_monitor() {
+        let mut test = TestApp::windowless();
+        test.backend_mut().monitors = testing::two_monitors();
+        *test.world().resource_mut::<Monitors>() = testing::two_monitors();
+        let entity = test.spawn_window(Window::default());
+        test.backend_mut()
+            .set_outer_position(entity, IVec2::new(480, 270))
+            .unwrap();
+
+        // A quarter into the 1080p monitor is a quarter into the 4K one
+        test.send(MoveWindowToMonitorEvent {
+            entity,
+            monitor: MonitorSelection::Index(1),
+        });
+        test.step();
+        let expected = IVec2::new(1920 + 960, 540);
+        assert_eq!(test.backend().outer_position(entity), Some(expected));
+        assert_eq!(
+            test.world().get::<Window>(entity).unwrap().position,
+            Some(expected)
+        );
+        let moves = test.events::<WindowMovedEvent>();
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].position, expected);
+
+        // The monitor was unplugged, the primary one is used instead
+        test.send(MoveWindowToMonitorEvent {
+            entity,
+            monitor: MonitorSelection::Index(2),
+        });
+        test.step();
+        assert_eq!(
+            test.backend().outer_position(entity),
+            Some(IVec2::new(480, 270))
+        );
+    }
+
+    #[test]
+    fn cursor_grab_is_restored_when_focus_returns() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window {
+            cursor_grab_mode: CursorGrabMode::Locked,
+            ..Default::default()
+        });
+
+        apply_focus_change::<MockBackend>(test.world(), entity, false);
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert!(!window.focused && !window.cursor_grab_active);
+
+        test.backend_mut()
+            .windows
+            .get_mut(&entity)
+            .unwrap()
+            .cursor_grab_mode = CursorGrabMode::None;
+        apply_focus_change::<MockBackend>(test.world(), entity, true);
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert!(window.focused && window.cursor_grab_active);
+        assert_eq!(
+            test.backend().windows[&entity].cursor_grab_mode,
+            CursorGrabMode::Locked
+        );
+    }
+
+    #[test]
+    fn recreated_window_is_announced_first() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+
+        test.send(RecreateWindowEvent { entity });
+        test.step();
+        assert_eq!(test.events::<WindowClosingEvent>().len(), 1);
+        assert_eq!(test.events::<WindowDestroyedEvent>().len(), 1);
+        assert_eq!(test.events::<WindowCreatedEvent>().len(), 1);
+        assert_eq!(test.backend().destroyed, [entity]);
+        assert_eq!(test.backend().created, [entity, entity]);
+        assert!(test.world().resource::<Windows>().contains(entity));
+    }
+
+    #[test]
+    fn recreated_window_waits_for_deferred_destroy() {
+        let mut test = TestApp::new(WindowPlugin {
+            primary_window: None,
+            deferred_destroy: true,
+            ..Default::default()
+        });
+        let entity = test.spawn_window(Window::default());
+
+        test.send(RecreateWindowEvent { entity });
+        test.step();
+        assert_eq!(test.events::<WindowClosingEvent>().len(), 1);
+        assert!(test.backend().destroyed.is_empty());
+
+        test.step();
+        assert!(test.events::<WindowClosingEvent>().is_empty());
+        assert_eq!(test.backend().destroyed, [entity]);
+        assert_eq!(test.backend().created, [entity, entity]);
+        assert_eq!(test.events::<WindowCreatedEvent>().len(), 1);
+    }
+
+    #[test]
+    fn exclusive_fullscreen_is_minimized_on_focus_loss() {
+        let mut test = TestApp::windowless();
+        let fullscreen = test.spawn_window(Window {
+            mode: WindowMode::Fullscreen(MonitorSelection::Primary, Default::default()),
+            ..Default::default()
+        });
+        let borderless = test.spawn_window(Window {
+            mode: WindowMode::BorderlessFullscreen(MonitorSelection::Primary),
+            ..Default::default()
+        });
+
+        apply_focus_change::<MockBackend>(test.world(), fullscreen, false);
+        apply_focus_change::<MockBackend>(test.world(), borderless, false);
+        assert!(test.backend().windows[&fullscreen].minimized);
+        assert!(!test.backend().windows[&borderless].minimized);
+
+        // Opted out on the window
+        test.backend_mut().set_minimized(fullscreen, false);
+        test.world()
+            .get_mut::<Window>(fullscreen)
+            .unwrap()
+            .minimize_on_focus_loss = false;
+        apply_focus_change::<MockBackend>(test.world(), fullscreen, false);
+        assert!(!test.backend().windows[&fullscreen].minimized);
+    }
+
+    #[test]
+    fn scale_factor_follows_the_os_unless_overridden() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+
+        assert_eq!(apply_scale_factor_change(test.world(), entity, 2.0), None);
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(window.scale_factor(), 2.0);
+        assert_eq!(test.events::<ScaleFactorChangedEvent>().len(), 1);
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .resolution
+            .set_scale_factor_override(Some(1.5));
+        test.step();
+        let sent = test.events::<ScaleFactorChangedEvent>();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].scale_factor, 1.5);
+        let sent = sent.len();
+        let size = apply_scale_factor_change(test.world(), entity, 3.0);
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(window.scale_factor(), 1.5);
+        assert_eq!(window.resolution.base_scale_factor(), 3.0);
+        assert_eq!(size, Some(window.resolution.physical_size()));
+        assert_eq!(test.events::<ScaleFactorChangedEvent>().len(), sent);
+    }
+
+    #[test]
+    fn loop_waits_while_there_are_no_windows() {
+        let mut test = TestApp::new(WindowPlugin {
+            exit_condition: ExitCondition::DontExit,
+            update_mode: UpdateMode::Continuous,
+            ..Default::default()
+        });
+        test.step();
+        assert_eq!(next_control_flow(test.world()), ControlFlow::Poll);
+
+        let primary = test
+            .world()
+            .resource::<PrimaryWindowEntity>()
+            .get()
+            .unwrap();
+        test.world().despawn(primary);
+        test.step();
+        assert!(test.events::<AppExit>().is_empty());
+        assert_eq!(next_control_flow(test.world()), ControlFlow::Wait);
+
+        test.spawn_window(Window::default());
+        assert_eq!(next_control_flow(test.world()), ControlFlow::Poll);
+    }
+
+    #[test]
+    fn resize_reports_physical_and_logical_sizes() {
+        let mut test = TestApp::windowless();
+        test.backend_mut().scale_factor = Some(2.0);
+        let entity = test.spawn_window(Window::default());
+
+        resize_window::<MockBackend>(test.world(), entity, PhysicalSize::new(1600, 1200));
+        let resizes = test.events::<WindowResizedEvent>();
+        assert_eq!(resizes.len(), 1);
+        assert_eq!(resizes[0].physical_size, UVec2::new(1600, 1200));
+        assert_eq!(resizes[0].logical_size, UVec2::new(800, 600));
+        assert_eq!(resizes[0].scale_factor, 2.0);
+
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(
+            (window.resolution.width, window.resolution.height),
+            (800, 600)
+        );
+        assert_eq!(window.resolution.physical_size(), UVec2::new(1600, 1200));
+    }
+
+    #[test]
+    fn ignoring_dpi_scaling_keeps_physical_pixels() {
+        let mut test = TestApp::new(WindowPlugin {
+            primary_window: None,
+            ignore_dpi_scaling: true,
+            ..Default::default()
+        });
+        test.backend_mut().scale_factor = Some(2.0);
+        let entity = test.spawn_window(Window::default());
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(window.resolution.scale_factor_override(), Some(1.0));
+        assert_eq!(window.scale_factor(), 1.0);
+
+        resize_window::<MockBackend>(test.world(), entity, PhysicalSize::new(1600, 1200));
+        let resizes = test.events::<WindowResizedEvent>();
+        assert_eq!(resizes[0].physical_size, UVec2::new(1600, 1200));
+        assert_eq!(resizes[0].logical_size, UVec2::new(1600, 1200));
+        assert_eq!(resizes[0].scale_factor, 1.0);
+
+        let position = PhysicalPosition::new(101.0, 75.5);
+        assert_eq!(
+            logical_cursor_position(test.world(), entity, position, || 2.0),
+            Vec2::new(101.0, 75.5)
+        );
+
+        // Overriding the scale factor again is refused
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .resolution
+            .set_scale_factor_override(Some(2.0));
+        test.step();
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(window.resolution.scale_factor_override(), Some(1.0));
+    }
+
+    #[test]
+    fn cursor_moves_alone_dont_wake_a_low_power_loop() {
+        // SAFETY: only compared against other ids, never passed to the platform
+        let (window_id, device_id) = unsafe {
+            (
+                winit::window::WindowId::dummy(),
+                winit::event::DeviceId::dummy(),
+            )
+        };
+        let window_event = |event| Event::<RunnerEvent>::WindowEvent { window_id, event };
+        let cursor_moved = window_event(WindowEvent::CursorMoved {
+            device_id,
+            position: winit::dpi::PhysicalPosition::new(10.0, 20.0),
+        });
+        let click = window_event(WindowEvent::MouseInput {
+            device_id,
+            state: ElementState::Pressed,
+            button: winit::event::MouseButton::Left,
+        });
+
+        assert!(WakeFilter::default().wakes(&cursor_moved));
+        assert!(!WakeFilter::LOW_POWER.wakes(&cursor_moved));
+        assert!(!WakeFilter::LOW_POWER.wakes(&Event::AboutToWait));
+        assert!(WakeFilter::LOW_POWER.wakes(&click));
+    }
+
+    #[test]
+    fn resume_time_reached_runs_a_frame() {
+        // SAFETY: only compared against other ids, never passed to the platform
+        let (window_id, device_id) = unsafe {
+            (
+                winit::window::WindowId::dummy(),
+                winit::event::DeviceId::dummy(),
+            )
+        };
+        let cursor_moved = Event::<RunnerEvent>::WindowEvent {
+            window_id,
+            event: WindowEvent::CursorMoved {
+                device_id,
+                position: winit::dpi::PhysicalPosition::new(10.0, 20.0),
+            },
+        };
+        let start = Instant::now();
+        let later = start + Duration::from_secs(60);
+        // The events of one loop iteration up to `AboutToWait`, like the runner folds them
+        let wakes = |iteration: [Event<RunnerEvent>; 3]| {
+            iteration.iter().fold(false, |wake, event| {
+                wake | WakeFilter::LOW_POWER.wakes(event)
+            })
+        };
+
+        // The cursor moved before the `wait` of `UpdateMode::Reactive` passed
+        assert!(!wakes([
+            Event::NewEvents(StartCause::WaitCancelled {
+                start,
+                requested_resume: Some(later),
+            }),
+            cursor_moved.clone(),
+            Event::AboutToWait,
+        ]));
+        // Nothing happened until the timeout
+        assert!(wakes([
+            Event::NewEvents(StartCause::ResumeTimeReached {
+                start,
+                requested_resume: later,
+            }),
+            Event::AboutToWait,
+            Event::AboutToWait,
+        ]));
+        // The cursor moved, but only handled after the timeout
+        assert!(wakes([
+            Event::NewEvents(StartCause::WaitCancelled {
+                start,
+                requested_resume: Some(start),
+            }),
+            cursor_moved,
+            Event::AboutToWait,
+        ]));
+    }
+
+    #[test]
+    fn fullscreen_toggled_by_the_os_is_picked_up() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+
+        // The user pressed the fullscreen shortcut of the OS, which only shows up as a resize
+        let expected = WindowMode::BorderlessFullscreen(MonitorSelection::Current);
+        test.backend_mut().windows.get_mut(&entity).unwrap().mode = expected;
+        resize_window::<MockBackend>(test.world(), entity, PhysicalSize::new(1920, 1080));
+        assert_eq!(test.world().get::<Window>(entity).unwrap().mode, expected);
+        let changes = test.events::<WindowModeChangedEvent>();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].mode, expected);
+
+        // Not sent back to the backend as a change of `Window::mode`
+        test.backend_mut().windows.get_mut(&entity).unwrap().mode = WindowMode::Windowed;
+        test.step();
+        assert_eq!(test.backend().windows[&entity].mode, WindowMode::Windowed);
+    }
+
+    /// A press of the W key and what it typed, like winit reports it
+    fn key_w(entity: Entity, state: ElementState, repeat: bool) -> KeyboardInputEvent {
+        KeyboardInputEvent {
+            entity,
+            logical_key: winit::keyboard::Key::Character("w".into()),
+            physical_key: PhysicalKey::Code(KeyCode::KeyW),
+            state,
+            repeat,
+        }
+    }
+
+    #[test]
+    fn filtered_key_repeats_only_send_the_press() {
+        let mut test = TestApp::new(WindowPlugin {
+            primary_window: None,
+            filter_key_repeat: true,
+            ..Default::default()
+        });
+        let entity = test.spawn_window(Window::default());
+
+        dispatch_key_input(
+            test.world(),
+            key_w(entity, ElementState::Pressed, false),
+            Some("w".to_string()),
+        );
+        for _ in 0..3 {
+            dispatch_key_input(
+                test.world(),
+                key_w(entity, ElementState::Pressed, true),
+                Some("w".to_string()),
+            );
+        }
+        let key_events = test.events::<KeyboardInputEvent>();
+        assert_eq!(key_events.len(), 1);
+        assert!(!key_events[0].repeat);
+        assert_eq!(test.events::<TextInputEvent>().len(), 4);
+        assert!(test
+            .world()
+            .resource::<ButtonInput<KeyCode>>()
+            .just_pressed(KeyCode::KeyW));
+    }
+
+    #[test]
+    fn azerty_keys_keep_their_position_and_character() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+
+        // The key in the position of Q on a US layout types an A on AZERTY
+        dispatch_key_input(
+            test.world(),
+            KeyboardInputEvent {
+                entity,
+                logical_key: winit::keyboard::Key::Character("a".into()),
+                physical_key: PhysicalKey::Code(KeyCode::KeyQ),
+                state: ElementState::Pressed,
+                repeat: false,
+            },
+            Some("a".to_string()),
+        );
+        let key_events = test.events::<KeyboardInputEvent>();
+        assert_eq!(key_events.len(), 1);
+        assert_eq!(
+            key_events[0].logical_key,
+            winit::keyboard::Key::Character("a".into())
+        );
+        assert_eq!(key_events[0].physical_key, PhysicalKey::Code(KeyCode::KeyQ));
+
+        // Bindings to positions like WASD follow the physical key
+        let keyboard = test.world().resource::<ButtonInput<KeyCode>>();
+        assert!(keyboard.pressed(KeyCode::KeyQ));
+        assert!(!keyboard.pressed(KeyCode::KeyA));
+    }
+
+    #[test]
+    fn right_shift_only_sets_the_right_side() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        let shift_right = |state| KeyboardInputEvent {
+            entity,
+            logical_key: winit::keyboard::Key::Named(winit::keyboard::NamedKey::Shift),
+            physical_key: PhysicalKey::Code(KeyCode::ShiftRight),
+            state,
+            repeat: false,
+        };
+
+        // The OS reports the combined state separately, like `ModifiersChanged` does
+        test.world()
+            .resource_mut::<Modifiers>()
+            .set_state(winit::keyboard::ModifiersState::SHIFT);
+        dispatch_key_input(test.world(), shift_right(ElementState::Pressed), None);
+        let modifiers = *test.world().resource::<Modifiers>();
+        assert!(modifiers.shift());
+        assert!(modifiers.right().shift);
+        assert!(!modifiers.left().shift);
+
+        dispatch_key_input(test.world(), shift_right(ElementState::Released), None);
+        assert!(!test.world().resource::<Modifiers>().right().shift);
+    }
+
+    #[test]
+    fn input_capture_keeps_window_events_flowing() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        test.world().resource_mut::<InputCapture>().enabled = false;
+
+        dispatch_key_input(
+            test.world(),
+            key_w(entity, ElementState::Pressed, false),
+            Some("w".to_string()),
+        );
+        assert!(test.events::<KeyboardInputEvent>().is_empty());
+        assert!(test.events::<TextInputEvent>().is_empty());
+
+        resize_window::<MockBackend>(test.world(), entity, PhysicalSize::new(640, 480));
+        apply_focus_change::<MockBackend>(test.world(), entity, true);
+        assert_eq!(test.events::<WindowResizedEvent>().len(), 1);
+        assert!(test.world().get::<Window>(entity).unwrap().focused);
+
+        test.world().resource_mut::<InputCapture>().enabled = true;
+        dispatch_key_input(
+            test.world(),
+            key_w(entity, ElementState::Released, false),
+            None,
+        );
+        assert_eq!(test.events::<KeyboardInputEvent>().len(), 1);
+    }
+
+    #[test]
+    fn user_input_resets_the_idle_time() {
+        let mut test = TestApp::windowless();
+        test.step();
+        let started = test.world().resource::<LastInputTime>().last_input();
+
+        std::thread::sleep(Duration::from_millis(20));
+        record_user_input(test.world(), &WindowEvent::Focused(true));
+        test.step();
+        let last_input_time = test.world().resource::<LastInputTime>();
+        assert_eq!(last_input_time.last_input(), started);
+        assert!(last_input_time.idle_duration() >= Duration::from_millis(20));
+
+        let click = WindowEvent::MouseInput {
+            // SAFETY: only compared against other ids, never passed to the platform
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            state: ElementState::Pressed,
+            button: winit::event::MouseButton::Left,
+        };
+        record_user_input(test.world(), &click);
+        test.step();
+        let last_input_time = test.world().resource::<LastInputTime>();
+        assert!(last_input_time.last_input() > started);
+        assert!(last_input_time.idle_duration() < Duration::from_millis(20));
+    }
+}
@@ -1,21 +1,1469 @@
-use crate::core::window::components::Window;
+use crate::core::window::backend::WindowBackend;
+use crate::core::window::components::{
+    MonitorSelection, SafeInsets, VideoModeSelection, Window, WindowIcon, WindowMode,
+    WindowResizeConstraints,
+};
+use crate::core::window::error::WindowError;
+use crate::core::window::events::RunnerEvent;
+use crate::core::window::{ExitPredicate, UpdateMode, WakeFilter, LOG_TARGET};
 use bevy_ecs::prelude::{Entity, Resource};
-use log::info;
-use std::collections::HashMap;
+use bevy_ecs::system::{NonSend, Res, SystemParam};
+#[cfg(windows)]
+use bevy_math::Rect;
+use bevy_math::{IVec2, UVec2, Vec2};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
 use std::marker::PhantomData;
-use winit::dpi::LogicalSize;
-use winit::window::{Icon, WindowBuilder};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::error::ExternalError;
+use winit::event::MouseButton;
+#[cfg(target_os = "linux")]
+use winit::event_loop::AsyncRequestSerial;
+use winit::event_loop::{ControlFlow, EventLoopProxy};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+use winit::monitor::MonitorHandle;
+#[cfg(target_os = "linux")]
+use winit::window::ActivationToken;
+use winit::window::{CursorGrabMode, Fullscreen, Icon, Theme, WindowBuilder, WindowButtons};
 
-/// Resource to keep track of the number of primary windows
-/// Used in a system to make sure there is only ever one primary window
+/// How many windows there are, counted once per frame after windows are closed in `Update`
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowCounts {
+    pub(crate) total: usize,
+    pub(crate) primary: usize,
+}
+
+#[allow(dead_code)]
+impl WindowCounts {
+    /// Every entity with a `Window` component
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Windows with the `PrimaryWindow` component, either 0 or 1
+    pub fn primary(&self) -> usize {
+        self.primary
+    }
+
+    /// Windows without the `PrimaryWindow` component
+    pub fn secondary(&self) -> usize {
+        self.total - self.primary
+    }
+}
+
+/// The current primary window entity, see `PrimaryWindowChangedEvent`
+/// `None` while there is no primary window
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrimaryWindowEntity(pub(crate) Option<Entity>);
+
+#[allow(dead_code)]
+impl PrimaryWindowEntity {
+    pub fn get(&self) -> Option<Entity> {
+        self.0
+    }
+}
+
+/// Which window has which `WindowName`, updated by `u_index_window_names`
+///
+/// Names should be unique. If several windows share a name, the one that got it first keeps it.
+/// Between windows named in the same frame the lower entity wins.
+#[derive(Resource, Default, Clone, Debug)]
+pub struct WindowNames {
+    pub(crate) names: HashMap<String, Entity>,
+    /// The order in which the windows got their current name
+    pub(crate) named_at: HashMap<Entity, u64>,
+    pub(crate) next_order: u64,
+}
+
+#[allow(dead_code)]
+impl WindowNames {
+    pub fn find_window_by_name(&self, name: &str) -> Option<Entity> {
+        self.names.get(name).copied()
+    }
+}
+
+/// A monitor connected to the system
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    pub name: Option<String>,
+    /// Position of the top left corner on the desktop in physical pixels
+    pub position: IVec2,
+    /// Size of the monitor in physical pixels
+    pub size: UVec2,
+    pub scale_factor: f64,
+    /// `None` if the platform doesn't report the refresh rate
+    pub refresh_rate_millihertz: Option<u32>,
+    /// The video modes that can be used for exclusive fullscreen
+    /// Sorted by resolution, then refresh rate and bit depth, without duplicates
+    pub video_modes: Vec<VideoMode>,
+}
+
+impl Monitor {
+    fn from_handle(handle: &MonitorHandle) -> Self {
+        let position = handle.position();
+        let size = handle.size();
+        Monitor {
+            name: handle.name(),
+            position: IVec2::new(position.x, position.y),
+            size: UVec2::new(size.width, size.height),
+            scale_factor: handle.scale_factor(),
+            refresh_rate_millihertz: handle.refresh_rate_millihertz(),
+            video_modes: sorted_video_modes(
+                handle
+                    .video_modes()
+                    .map(|video_mode| VideoMode::from_winit(&video_mode)),
+            ),
+        }
+    }
+
+    /// Every resolution of the video modes with the refresh rates it supports in millihertz, both
+    /// ascending, e.g. to fill the resolution and refresh rate dropdowns of a settings menu
+    #[allow(dead_code)]
+    pub fn resolutions(&self) -> Vec<(UVec2, Vec<u32>)> {
+        let mut resolutions: Vec<(UVec2, Vec<u32>)> = Vec::new();
+        for video_mode in &self.video_modes {
+            match resolutions.last_mut() {
+                Some((size, refresh_rates)) if *size == video_mode.size => {
+                    if refresh_rates.last() != Some(&video_mode.refresh_rate_millihertz) {
+                        refresh_rates.push(video_mode.refresh_rate_millihertz);
+                    }
+                }
+                _ => resolutions.push((video_mode.size, vec![video_mode.refresh_rate_millihertz])),
+            }
+        }
+        resolutions
+    }
+}
+
+/// Sorts video modes by resolution, then refresh rate and bit depth, and removes duplicates
+/// winit lists them in whatever order the platform reports, sometimes with the same mode twice
+fn sorted_video_modes(video_modes: impl Iterator<Item = VideoMode>) -> Vec<VideoMode> {
+    let mut video_modes: Vec<VideoMode> = video_modes.collect();
+    video_modes.sort_by_key(|video_mode| {
+        (
+            video_mode.size.x,
+            video_mode.size.y,
+            video_mode.refresh_rate_millihertz,
+            video_mode.bit_depth,
+        )
+    });
+    video_modes.dedup();
+    video_modes
+}
+
+/// A video mode a monitor supports in exclusive fullscreen
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VideoMode {
+    /// Resolution in physical pixels
+    pub size: UVec2,
+    pub bit_depth: u16,
+    pub refresh_rate_millihertz: u32,
+}
+
+impl VideoMode {
+    fn from_winit(video_mode: &winit::monitor::VideoMode) -> Self {
+        let size = video_mode.size();
+        VideoMode {
+            size: UVec2::new(size.width, size.height),
+            bit_depth: video_mode.bit_depth(),
+            refresh_rate_millihertz: video_mode.refresh_rate_millihertz(),
+        }
+    }
+}
+
+/// All monitors connected to the system, filled in when the event loop starts
+/// The monitors are in the order winit lists them in
+#[derive(Resource, Default, Clone, Debug)]
+pub struct Monitors {
+    pub monitors: Vec<Monitor>,
+    /// Index of the primary monitor
+    /// `None` if the platform has no primary monitor (like Wayland)
+    pub primary: Option<usize>,
+}
+
+#[allow(dead_code)]
+impl Monitors {
+    /// Builds the monitor list from winit monitor handles
+    pub fn from_handles(handles: &[MonitorHandle], primary: Option<&MonitorHandle>) -> Self {
+        Monitors {
+            monitors: handles.iter().map(Monitor::from_handle).collect(),
+            primary: primary
+                .and_then(|primary| handles.iter().position(|handle| handle == primary)),
+        }
+    }
+
+    /// The primary monitor, if the platform has one
+    pub fn primary(&self) -> Option<&Monitor> {
+        self.monitors.get(self.primary?)
+    }
+
+    /// The monitor containing a point on the desktop, in physical pixels
+    /// `None` if the point isn't on any monitor
+    ///
+    /// Useful to open popups and tooltips on the same monitor as the cursor.
+    pub fn monitor_at(&self, point: IVec2) -> Option<&Monitor> {
+        self.monitors.get(self.index_at(point)?)
+    }
+
+    /// The index of the monitor containing a point on the desktop, in physical pixels
+    pub fn index_at(&self, point: IVec2) -> Option<usize> {
+        self.monitors.iter().position(|monitor| {
+            let offset = point - monitor.position;
+            offset.x >= 0
+                && offset.y >= 0
+                && (offset.x as u32) < monitor.size.x
+                && (offset.y as u32) < monitor.size.y
+        })
+    }
+
+    /// Where a window with this outer position and size ends up when it moves to monitor `to`
+    ///
+    /// The offset from the monitor the window is on is scaled to the size of the new monitor and
+    /// clamped so the window stays on it. Windows that aren't on any monitor are centered.
+    pub fn position_on(&self, to: usize, position: IVec2, size: UVec2) -> IVec2 {
+        let target = &self.monitors[to];
+        let free = target.size.as_ivec2() - size.as_ivec2();
+        let Some(from) = self.index_at(position).map(|index| &self.monitors[index]) else {
+            return target.position + free / 2;
+        };
+
+        let relative = (position - from.position).as_vec2() / from.size.as_vec2().max(Vec2::ONE);
+        let offset = (relative * target.size.as_vec2()).as_ivec2();
+        target.position + offset.min(free).max(IVec2::ZERO)
+    }
+
+    /// Finds the index of the monitor a selection refers to
+    ///
+    /// `current` is the index of the monitor the window is on, if known.
+    /// Selections that can't be resolved fall back to the primary monitor with a warning.
+    pub fn resolve(&self, selection: MonitorSelection, current: Option<usize>) -> Option<usize> {
+        let selected = match selection {
+            MonitorSelection::Current => current.or(self.primary),
+            MonitorSelection::Primary => self.primary,
+            MonitorSelection::Index(index) => (index < self.monitors.len()).then_some(index),
+        };
+
+        if selected.is_none() {
+            warn!(target: LOG_TARGET,
+                "Could not find monitor {:?}, falling back to the primary monitor",
+                selection
+            );
+            return self.primary;
+        }
+        selected
+    }
+}
+
+/// How many frames in a row a failing cursor grab is tried before giving up
+/// Wayland can refuse to grab the cursor right after a window is created
+pub(crate) const CURSOR_GRAB_ATTEMPTS: u32 = 3;
+
+/// Converts a window mode to the fullscreen setting of winit, picking the monitor it targets
+///
+/// `handles` must be every available monitor, `current` the monitor the window is on (if any).
+pub(crate) fn winit_fullscreen(
+    mode: WindowMode,
+    handles: &[MonitorHandle],
+    primary: Option<&MonitorHandle>,
+    current: Option<&MonitorHandle>,
+) -> Option<Fullscreen> {
+    let monitors = Monitors::from_handles(handles, primary);
+    let current = current.and_then(|current| handles.iter().position(|handle| handle == current));
+
+    match mode {
+        WindowMode::Windowed => None,
+        WindowMode::BorderlessFullscreen(selection) => Some(Fullscreen::Borderless(
+            monitors
+                .resolve(selection, current)
+                .map(|index| handles[index].clone()),
+        )),
+        WindowMode::Fullscreen(selection, video_mode_selection) => {
+            let Some(index) = monitors.resolve(selection, current) else {
+                warn!(target: LOG_TARGET, "No monitor available for exclusive fullscreen, staying windowed");
+                return None;
+            };
+
+            let video_mode = match video_mode_selection {
+                // The best video mode has the highest resolution, then bit depth, then refresh rate
+                VideoModeSelection::Best => handles[index].video_modes().max_by_key(|video_mode| {
+                    let size = video_mode.size();
+                    (
+                        size.width * size.height,
+                        video_mode.bit_depth(),
+                        video_mode.refresh_rate_millihertz(),
+                    )
+                }),
+                VideoModeSelection::Specific(wanted) => {
+                    closest_video_mode(handles[index].video_modes(), wanted, VideoMode::from_winit)
+                }
+            };
+            match video_mode {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                None => {
+                    warn!(target: LOG_TARGET, "Monitor has no video modes for exclusive fullscreen, using borderless");
+                    Some(Fullscreen::Borderless(Some(handles[index].clone())))
+                }
+            }
+        }
+    }
+}
+
+/// The window mode matching the fullscreen state winit reports, e.g. after the user toggled it
+/// with a shortcut of the OS
+///
+/// Returns `None` if `current` already is that kind of mode, so the monitor it selects is kept.
+pub(crate) fn window_mode_from_winit(
+    fullscreen: Option<Fullscreen>,
+    current: WindowMode,
+) -> Option<WindowMode> {
+    match (fullscreen, current) {
+        (None, WindowMode::Windowed)
+        | (Some(Fullscreen::Borderless(_)), WindowMode::BorderlessFullscreen(_))
+        | (Some(Fullscreen::Exclusive(_)), WindowMode::Fullscreen(..)) => None,
+        (None, _) => Some(WindowMode::Windowed),
+        (Some(Fullscreen::Borderless(_)), _) => {
+            Some(WindowMode::BorderlessFullscreen(MonitorSelection::Current))
+        }
+        (Some(Fullscreen::Exclusive(video_mode)), _) => Some(WindowMode::Fullscreen(
+            MonitorSelection::Current,
+            VideoModeSelection::Specific(VideoMode::from_winit(&video_mode)),
+        )),
+    }
+}
+
+/// The size of the icon shown in the title bar, which the closest icon is picked for
+#[cfg_attr(not(windows), allow(dead_code))]
+const TITLE_BAR_ICON_SIZE: u32 = 32;
+
+/// Every icon of a window with data matching its size, the main icon first
+fn window_icons(window: &Window) -> Vec<WindowIcon> {
+    let main_icon = window.icon_data.as_ref().map(|data| WindowIcon {
+        width: window.icon_width,
+        height: window.icon_height,
+        data: data.clone(),
+    });
+    main_icon
+        .into_iter()
+        .chain(window.icons.iter().cloned())
+        .filter(|icon| {
+            let valid = icon.width > 0
+                && icon.height > 0
+                && icon.data.len() as u64 == icon.width as u64 * icon.height as u64 * 4;
+            if !valid {
+                warn!(target: LOG_TARGET,
+                    "Skipping a {}x{} window icon with {} bytes of data",
+                    icon.width, icon.height, icon.data.len()
+                );
+            }
+            valid
+        })
+        .collect()
+}
+
+/// The icon whose larger side is closest to `size`, preferring larger icons on ties
+fn closest_icon(icons: &[WindowIcon], size: u32) -> Option<&WindowIcon> {
+    icons.iter().min_by_key(|icon| {
+        (
+            icon.width.max(icon.height).abs_diff(size),
+            u32::MAX - icon.width,
+        )
+    })
+}
+
+fn winit_icon(icon: Option<&WindowIcon>) -> Option<Icon> {
+    let icon = icon?;
+    match Icon::from_rgba(icon.data.clone(), icon.width, icon.height) {
+        Ok(icon) => Some(icon),
+        Err(err) => {
+            warn!(target: LOG_TARGET, "Could not use the window icon: {err}");
+            None
+        }
+    }
+}
+
+/// Finds the video mode matching `wanted`, or the closest one if the monitor doesn't support it
+/// Closest means the nearest size, then refresh rate, then bit depth
+///
+/// `describe` reads a platform video mode, like `VideoMode::from_winit`.
+fn closest_video_mode<T>(
+    video_modes: impl Iterator<Item = T>,
+    wanted: VideoMode,
+    describe: impl Fn(&T) -> VideoMode,
+) -> Option<T> {
+    let video_mode = video_modes.min_by_key(|video_mode| {
+        let candidate = describe(video_mode);
+        (
+            candidate.size.x.abs_diff(wanted.size.x) + candidate.size.y.abs_diff(wanted.size.y),
+            candidate
+                .refresh_rate_millihertz
+                .abs_diff(wanted.refresh_rate_millihertz),
+            candidate.bit_depth.abs_diff(wanted.bit_depth),
+        )
+    })?;
+
+    if describe(&video_mode) != wanted {
+        warn!(target: LOG_TARGET,
+            "Video mode {:?} is not supported by the monitor, using the closest one {:?}",
+            wanted,
+            describe(&video_mode)
+        );
+    }
+    Some(video_mode)
+}
+
+/// Makes a window title safe to hand to the platform
+/// Line breaks and tabs become spaces and other control characters like `\0` are dropped, as
+/// some platforms cut the title off or show garbage for them
+pub(crate) fn sanitize_title(title: &str) -> Cow<'_, str> {
+    if !title.chars().any(char::is_control) {
+        return Cow::Borrowed(title);
+    }
+
+    debug!(target: LOG_TARGET, "Window title {:?} contains control characters, removing them", title);
+    Cow::Owned(
+        title
+            .chars()
+            .filter_map(|c| match c {
+                '\n' | '\r' | '\t' => Some(' '),
+                c if c.is_control() => None,
+                c => Some(c),
+            })
+            .collect(),
+    )
+}
+
+/// How the event loop waits for the next iteration
+/// The runner reads this at the end of every iteration, so systems can change it at any time
+///
+/// For example a video player can switch to `UpdateMode::Continuous` while playing and back to
+/// `UpdateMode::Reactive` once it's paused.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ControlFlowSettings {
+    /// The mode used for every iteration
+    /// Starts out as `WindowPlugin::update_mode`
+    pub update_mode: UpdateMode,
+    /// Overrides `update_mode` for the next iteration only
+    pub next_iteration: Option<UpdateMode>,
+}
+
+impl ControlFlowSettings {
+    pub fn new(update_mode: UpdateMode) -> Self {
+        ControlFlowSettings {
+            update_mode,
+            next_iteration: None,
+        }
+    }
+
+    /// The control flow for the next iteration, consuming the one-off override if there is one
+    /// Without any windows there is nothing to render, so the loop waits for the next event
+    /// instead of spinning, unless the app asked for another iteration
+    pub(crate) fn next_control_flow(&mut self, has_windows: bool) -> ControlFlow {
+        match self.next_iteration.take() {
+            Some(update_mode) => update_mode.control_flow(),
+            None if !has_windows => ControlFlow::Wait,
+            None => self.update_mode.control_flow(),
+        }
+    }
+}
+
+/// Every entity that currently has a native window, in the order they were created
+///
+/// This is kept in sync with `WinitWindows`, but as a normal resource it can be read without
+/// holding on to the main thread, for example to broadcast something to every window.
+#[derive(Resource, Default)]
+pub struct Windows(pub(crate) Vec<Entity>);
+
+#[allow(dead_code)]
+impl Windows {
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.0.contains(&entity)
+    }
+}
+
+/// Windows whose close requests are denied this frame
+/// Cleared once the requests of the frame are handled, so a window has to be denied every time
 #[derive(Resource, Default)]
-pub struct PrimaryWindowCount(pub u32);
+pub struct WindowCloseDenied(pub(crate) HashSet<Entity>);
+
+#[allow(dead_code)]
+impl WindowCloseDenied {
+    /// Keeps the window open, call this in `WindowCloseSet::Veto`
+    pub fn deny(&mut self, entity: Entity) {
+        self.0.insert(entity);
+    }
+
+    pub fn is_denied(&self, entity: Entity) -> bool {
+        self.0.contains(&entity)
+    }
+}
+
+/// Windows that were announced as closing and are destroyed in `WindowCloseSet::Destroy`
+#[derive(Resource, Default)]
+pub struct ClosingWindows {
+    pub(crate) announced: Vec<Entity>,
+    /// Announced in the last frame and destroyed in this one, see `WindowPlugin::deferred_destroy`
+    pub(crate) deferred: Vec<Entity>,
+    /// Windows that are created again once destroyed, see `RecreateWindowEvent`
+    pub(crate) recreating: Vec<Entity>,
+}
+
+/// App-wide defaults for the `Window` fields left at `None`, so multi-window apps don't have to
+/// repeat them on every window
+///
+/// A value set on a `Window` always wins over these. They are filled in when a window is
+/// created, so changing them later doesn't affect existing windows.
+///
+/// `vsync` is the default present mode: windows don't pick a present mode themselves, the
+/// renderer chooses one for its surface from `Window::vsync`. There is no default update mode,
+/// since the event loop updates all windows at once, see `ControlFlowSettings`.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowSettings {
+    /// See `Window::decorations`
+    pub decorations: bool,
+    /// The default present mode, see `Window::vsync`
+    pub vsync: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            decorations: true,
+            vsync: true,
+        }
+    }
+}
+
+impl WindowSettings {
+    /// Fills in the fields of a window that use the defaults
+    pub(crate) fn apply_defaults(&self, window: &mut Window) {
+        window.decorations.get_or_insert(self.decorations);
+        window.vsync.get_or_insert(self.vsync);
+    }
+}
+
+/// The settings of the primary window a player can tweak, e.g. loaded from a settings file
+///
+/// Insert or change this resource to apply it to the primary window, so reloading the file
+/// while the app runs updates the window. Fields missing from the file keep their defaults.
+#[allow(dead_code)]
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub title: String,
+    /// The size of the client area in logical pixels
+    pub width: u32,
+    pub height: u32,
+    pub mode: WindowMode,
+    /// `None` keeps the current setting of the window
+    pub vsync: Option<bool>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig::from_window(&Window::default())
+    }
+}
+
+#[allow(dead_code)]
+impl WindowConfig {
+    /// Takes the current settings of a window, e.g. to save them to the settings file
+    pub fn from_window(window: &Window) -> Self {
+        WindowConfig {
+            title: window.title.clone(),
+            width: window.resolution.width,
+            height: window.resolution.height,
+            mode: window.mode,
+            vsync: window.vsync,
+        }
+    }
+
+    /// Whether applying this config would change anything about `window`
+    pub(crate) fn differs_from(&self, window: &Window) -> bool {
+        let current = WindowConfig::from_window(window);
+        self.title != current.title
+            || (self.width, self.height) != (current.width, current.height)
+            || self.mode != current.mode
+            || self.vsync.is_some_and(|vsync| current.vsync != Some(vsync))
+    }
+
+    /// Writes the settings to a window, the window systems forward what changed to winit
+    pub(crate) fn apply_to(&self, window: &mut Window) {
+        window.title.clone_from(&self.title);
+        window.resolution.width = self.width;
+        window.resolution.height = self.height;
+        window.mode = self.mode;
+        // Leaving it out keeps what the window already has
+        if self.vsync.is_some() {
+            window.vsync = self.vsync;
+        }
+    }
+}
+
+/// A snapshot of the window subsystem for bug reports, refreshed by `DumpWindowDiagnosticsEvent`
+///
+/// It can be serialized, e.g. to JSON, and attached to an issue as is.
+#[allow(dead_code)]
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WindowDiagnostics {
+    /// The name of the `ExitCondition` of the `WindowPlugin`
+    pub exit_condition: String,
+    pub update_mode: UpdateMode,
+    pub windows: Vec<WindowSnapshot>,
+}
+
+/// The state of one window in `WindowDiagnostics`
+#[allow(dead_code)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WindowSnapshot {
+    pub entity: Entity,
+    /// The id of the winit window, `None` if it wasn't created yet
+    pub window_id: Option<u64>,
+    pub primary: bool,
+    pub title: String,
+    /// The size of the client area in logical pixels
+    pub size: UVec2,
+    pub scale_factor: f64,
+    pub mode: WindowMode,
+    pub focused: bool,
+    pub minimized: bool,
+}
+
+/// Whether the window module sends input events like `KeyboardInputEvent` to the app
+///
+/// Turn this off while something else takes over the input, like a native overlay or a
+/// debugger. The cursor position and `Modifiers` are still tracked so nothing jumps once it is
+/// turned back on, and events about the windows themselves keep coming. Releases still reach
+/// `ButtonInput`, so keys held while it is turned off don't stay pressed.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputCapture {
+    pub enabled: bool,
+}
+
+impl Default for InputCapture {
+    fn default() -> Self {
+        InputCapture { enabled: true }
+    }
+}
+
+/// The modifier keys currently held down on the keyboard
+///
+/// The combined state comes from the OS, so it is right even if a key was pressed before a
+/// window had focus. The side of each key comes from the key events the windows receive.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Modifiers {
+    state: ModifiersState,
+    left: SideModifiers,
+    right: SideModifiers,
+}
+
+/// Which modifier keys on one side of the keyboard are held down
+#[allow(dead_code)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SideModifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    pub super_key: bool,
+}
+
+#[allow(dead_code)]
+impl Modifiers {
+    /// Whether either shift key is held down
+    pub fn shift(&self) -> bool {
+        self.state.shift_key()
+    }
+
+    /// Whether either control key is held down
+    pub fn control(&self) -> bool {
+        self.state.control_key()
+    }
+
+    /// Whether either alt key is held down
+    pub fn alt(&self) -> bool {
+        self.state.alt_key()
+    }
+
+    /// Whether either super key (Windows or Command key) is held down
+    pub fn super_key(&self) -> bool {
+        self.state.super_key()
+    }
+
+    /// The modifier keys held down on the left side of the keyboard
+    pub fn left(&self) -> SideModifiers {
+        self.left
+    }
+
+    /// The modifier keys held down on the right side of the keyboard
+    pub fn right(&self) -> SideModifiers {
+        self.right
+    }
+
+    /// Takes the combined state from the OS
+    /// Sides of modifiers the OS reports as released are released too, like after losing focus
+    pub(crate) fn set_state(&mut self, state: ModifiersState) {
+        self.state = state;
+        for side in [&mut self.left, &mut self.right] {
+            side.shift &= state.shift_key();
+            side.control &= state.control_key();
+            side.alt &= state.alt_key();
+            side.super_key &= state.super_key();
+        }
+    }
+
+    /// Tracks the side of a modifier key that was pressed or released
+    pub(crate) fn key_event(&mut self, physical_key: PhysicalKey, pressed: bool) {
+        let PhysicalKey::Code(key_code) = physical_key else {
+            return;
+        };
+        let key = match key_code {
+            KeyCode::ShiftLeft => &mut self.left.shift,
+            KeyCode::ShiftRight => &mut self.right.shift,
+            KeyCode::ControlLeft => &mut self.left.control,
+            KeyCode::ControlRight => &mut self.right.control,
+            KeyCode::AltLeft => &mut self.left.alt,
+            KeyCode::AltRight => &mut self.right.alt,
+            KeyCode::SuperLeft => &mut self.left.super_key,
+            KeyCode::SuperRight => &mut self.right.super_key,
+            _ => return,
+        };
+        *key = pressed;
+    }
+}
+
+/// Which buttons of type `T` are held down, like `ButtonInput<KeyCode>` for the keyboard and
+/// `ButtonInput<MouseButton>` for the mouse
+///
+/// The runner keeps it up to date from the window events and clears the `just_` sets after every
+/// frame, so `just_pressed` is true for exactly one frame. Everything is released when a window
+/// loses focus, since the release of a key held while tabbing out never arrives.
+#[derive(Resource, Clone, Debug)]
+pub struct ButtonInput<T: Copy + Eq + Hash + Send + Sync + 'static> {
+    pressed: HashSet<T>,
+    just_pressed: HashSet<T>,
+    just_released: HashSet<T>,
+}
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> Default for ButtonInput<T> {
+    fn default() -> Self {
+        ButtonInput {
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> ButtonInput<T> {
+    /// Whether the button is held down
+    pub fn pressed(&self, button: T) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    /// Whether the button was pressed since the last frame
+    pub fn just_pressed(&self, button: T) -> bool {
+        self.just_pressed.contains(&button)
+    }
+
+    /// Whether the button was released since the last frame
+    pub fn just_released(&self, button: T) -> bool {
+        self.just_released.contains(&button)
+    }
+
+    /// Whether any of the buttons is held down
+    pub fn any_pressed(&self, buttons: impl IntoIterator<Item = T>) -> bool {
+        buttons.into_iter().any(|button| self.pressed(button))
+    }
+
+    pub fn get_pressed(&self) -> impl Iterator<Item = &T> {
+        self.pressed.iter()
+    }
+
+    pub fn get_just_pressed(&self) -> impl Iterator<Item = &T> {
+        self.just_pressed.iter()
+    }
+
+    pub fn get_just_released(&self) -> impl Iterator<Item = &T> {
+        self.just_released.iter()
+    }
+
+    /// Key repeats don't count as another press
+    pub(crate) fn press(&mut self, button: T) {
+        if self.pressed.insert(button) {
+            self.just_pressed.insert(button);
+        }
+    }
+
+    pub(crate) fn release(&mut self, button: T) {
+        if self.pressed.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+
+    /// Releases every button held down, e.g. after losing focus
+    pub(crate) fn release_all(&mut self) {
+        self.just_released.extend(self.pressed.drain());
+    }
+
+    /// Called at the end of every frame
+    pub(crate) fn clear_just(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+/// A key or mouse button an action can be bound to
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// Maps named actions like `"jump"` to the keys and mouse buttons that trigger them
+///
+/// An action can have several bindings and is pressed while any of them is held down, see
+/// `Actions`. Change the bindings at any time to let players remap their controls, and
+/// serialize this resource to save them to and load them from a settings file.
+#[allow(dead_code)]
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    actions: BTreeMap<String, Vec<InputBinding>>,
+}
+
+#[allow(dead_code)]
+impl KeyBindings {
+    /// Adds a binding to an action, keeping the ones it already has
+    pub fn bind(&mut self, action: impl Into<String>, binding: InputBinding) {
+        let bindings = self.actions.entry(action.into()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replaces all bindings of an action, e.g. after the player picked a new key
+    pub fn rebind(
+        &mut self,
+        action: impl Into<String>,
+        bindings: impl IntoIterator<Item = InputBinding>,
+    ) {
+        let action = action.into();
+        self.actions.remove(&action);
+        for binding in bindings {
+            self.bind(action.clone(), binding);
+        }
+    }
+
+    /// Removes one binding from an action
+    pub fn unbind(&mut self, action: &str, binding: InputBinding) {
+        if let Some(bindings) = self.actions.get_mut(action) {
+            bindings.retain(|&bound| bound != binding);
+        }
+    }
+
+    /// Removes an action and all of its bindings
+    pub fn remove(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    /// The bindings of an action, empty if it has none
+    pub fn bindings(&self, action: &str) -> &[InputBinding] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    /// All actions and their bindings, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[InputBinding])> {
+        self.actions
+            .iter()
+            .map(|(action, bindings)| (action.as_str(), bindings.as_slice()))
+    }
+
+    /// The actions bound to this key or button
+    pub fn actions_for(&self, binding: InputBinding) -> impl Iterator<Item = &str> {
+        self.actions
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| action.as_str())
+    }
+
+    /// Bindings shared by more than one action with the actions using them, e.g. to warn the
+    /// player in a remapping menu
+    pub fn conflicts(&self) -> Vec<(InputBinding, Vec<&str>)> {
+        let mut conflicts: Vec<(InputBinding, Vec<&str>)> = Vec::new();
+        for bindings in self.actions.values() {
+            for &binding in bindings {
+                if conflicts.iter().any(|(conflict, _)| *conflict == binding) {
+                    continue;
+                }
+                let actions: Vec<&str> = self.actions_for(binding).collect();
+                if actions.len() > 1 {
+                    conflicts.push((binding, actions));
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+/// Which actions of `KeyBindings` are held down, updated from `ButtonInput` before every update
+///
+/// Like `ButtonInput` the `just_` states last for exactly one frame. An action tapped so quickly
+/// that it was pressed and released between two frames is both just pressed and just released.
+#[allow(dead_code)]
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Actions {
+    pub(crate) pressed: HashSet<String>,
+    pub(crate) just_pressed: HashSet<String>,
+    pub(crate) just_released: HashSet<String>,
+}
+
+#[allow(dead_code)]
+impl Actions {
+    pub fn pressed(&self, action: &str) -> bool {
+        self.pressed.contains(action)
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.just_pressed.contains(action)
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        self.just_released.contains(action)
+    }
+}
+
+/// Options of the `WindowPlugin` that the runner needs while the event loop runs
+#[derive(Resource, Clone, Debug)]
+pub struct RunnerSettings {
+    /// See `WindowPlugin::coalesce_resize_events`
+    pub coalesce_resize_events: bool,
+    /// See `WindowPlugin::filter_key_repeat`
+    pub filter_key_repeat: bool,
+    /// See `WindowPlugin::wait_for_first_frame`
+    pub wait_for_first_frame: bool,
+    /// See `WindowPlugin::reactive_wake_filter`
+    pub reactive_wake_filter: WakeFilter,
+    /// See `WindowPlugin::catch_update_panics`
+    pub catch_update_panics: bool,
+    /// See `WindowPlugin::window_creation_attempts`
+    pub window_creation_attempts: u32,
+    /// See `WindowPlugin::update_when_minimized`
+    pub update_when_minimized: bool,
+    /// See `WindowPlugin::exit_grace_frames`
+    pub exit_grace_frames: u32,
+    /// See `WindowPlugin::ignore_dpi_scaling`
+    pub ignore_dpi_scaling: bool,
+    /// See `WindowPlugin::deferred_destroy`
+    pub deferred_destroy: bool,
+}
+
+/// How often the idle timer of the OS is reset while the screensaver is inhibited
+const SCREENSAVER_RESET_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Keeps the display awake while any window has `Window::inhibit_screensaver` set
+#[derive(Resource, Default)]
+pub struct ScreensaverInhibitor {
+    last_reset: Option<Instant>,
+    #[cfg(not(windows))]
+    warned_unsupported: bool,
+}
+
+impl ScreensaverInhibitor {
+    /// Resets the idle timer of the OS if it hasn't been reset recently
+    pub(crate) fn keep_awake(&mut self) {
+        if self
+            .last_reset
+            .is_some_and(|last_reset| last_reset.elapsed() < SCREENSAVER_RESET_INTERVAL)
+        {
+            return;
+        }
+        self.last_reset = Some(Instant::now());
+
+        #[cfg(windows)]
+        {
+            use windows_sys::Win32::System::Power::{
+                SetThreadExecutionState, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED,
+            };
+
+            // Without ES_CONTINUOUS this only resets the idle timers once,
+            // so nothing is left behind if the app stops calling it
+            // SAFETY: SetThreadExecutionState has no preconditions
+            unsafe {
+                SetThreadExecutionState(ES_DISPLAY_REQUIRED | ES_SYSTEM_REQUIRED);
+            }
+        }
+
+        #[cfg(not(windows))]
+        if !self.warned_unsupported {
+            warn!(target: LOG_TARGET, "Inhibiting the screensaver is not supported on this platform");
+            self.warned_unsupported = true;
+        }
+    }
+
+    /// Stops keeping the display awake, the OS idle timer continues normally
+    pub(crate) fn allow_sleep(&mut self) {
+        self.last_reset = None;
+    }
+}
+
+/// How long the app took to start, measured from when the `WindowPlugin` was built
+/// Useful to find out why a window takes a while to show up
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct WindowStartupTiming {
+    pub(crate) plugin_built: Instant,
+    pub(crate) first_window_created: Option<Duration>,
+    pub(crate) first_update: Option<Duration>,
+}
+
+#[allow(dead_code)]
+impl WindowStartupTiming {
+    pub(crate) fn new() -> Self {
+        WindowStartupTiming {
+            plugin_built: Instant::now(),
+            first_window_created: None,
+            first_update: None,
+        }
+    }
+
+    /// Until the first native window was created, `None` before that
+    pub fn first_window_created(&self) -> Option<Duration> {
+        self.first_window_created
+    }
+
+    /// Until the first update finished, `None` before that
+    pub fn first_update(&self) -> Option<Duration> {
+        self.first_update
+    }
+
+    pub(crate) fn record_window_created(&mut self) {
+        self.first_window_created
+            .get_or_insert_with(|| self.plugin_built.elapsed());
+    }
+
+    /// Returns `true` the first time, when the numbers are complete
+    pub(crate) fn record_update(&mut self) -> bool {
+        if self.first_update.is_some() {
+            return false;
+        }
+        self.first_update = Some(self.plugin_built.elapsed());
+        true
+    }
+}
+
+/// How many frames `FrameTimings` keeps track of
+pub const FRAME_TIMINGS_CAPACITY: usize = 120;
+
+/// How long the most recent frames took to update
+/// Measured around `app.update()` by the runner, the newest frame is the last one
+#[derive(Resource, Default)]
+pub struct FrameTimings {
+    samples: VecDeque<Duration>,
+}
+
+#[allow(dead_code)]
+impl FrameTimings {
+    /// Records the duration of a frame, dropping the oldest one if the buffer is full
+    pub(crate) fn push(&mut self, frame_time: Duration) {
+        if self.samples.len() == FRAME_TIMINGS_CAPACITY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(frame_time);
+    }
+
+    /// The recorded frame times, oldest first
+    pub fn samples(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// The average of the recorded frame times
+    /// `None` if no frame has finished yet
+    pub fn average_frame_time(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+
+    /// The shortest recorded frame time
+    pub fn min_frame_time(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    /// The longest recorded frame time
+    pub fn max_frame_time(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+}
+
+/// The primary window that will be spawned in `PostStartup`
+/// Only exists when `WindowPlugin::defer_primary_window` is set, and until the window is spawned
+///
+/// Change or replace the window in a `Startup` system to configure it.
+/// Set it to `None` to not spawn a primary window at all.
+#[derive(Resource)]
+pub struct DeferredPrimaryWindow(pub Option<Window>);
+
+/// The grace period between `AppExit` and the actual exit, see `WindowPlugin::exit_grace_frames`
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct ExitGrace {
+    pub(crate) exit_requested: bool,
+    /// Set this once the app is done and can exit before the grace period is over
+    pub ready: bool,
+}
+
+#[allow(dead_code)]
+impl ExitGrace {
+    /// Whether `AppExit` was sent and the app exits soon
+    pub fn exit_requested(&self) -> bool {
+        self.exit_requested
+    }
+}
+
+/// While this exists, the exit condition isn't checked and the app keeps running without windows
+///
+/// Inserted by `EnterWindowlessEvent`, e.g. to switch to a server mode from a menu. Remove it
+/// to check the exit condition again, after spawning a new window if it needs one.
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct Windowless;
+
+/// When the user last used the keyboard, mouse or a touch screen on any window
+/// Useful to detect idle users, like for an attract mode or an automatic logout
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct LastInputTime {
+    pub(crate) last_input: Instant,
+    /// The start of the current frame
+    pub(crate) frame_start: Instant,
+}
+
+impl Default for LastInputTime {
+    fn default() -> Self {
+        let now = Instant::now();
+        LastInputTime {
+            last_input: now,
+            frame_start: now,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl LastInputTime {
+    /// When the last input event arrived, or when the app started if there was none yet
+    pub fn last_input(&self) -> Instant {
+        self.last_input
+    }
+
+    /// How long there has been no input as of the start of the current frame
+    pub fn idle_duration(&self) -> Duration {
+        self.frame_start.saturating_duration_since(self.last_input)
+    }
+}
+
+/// The predicate of `ExitCondition::Custom`, checked by `pu_exit_on_custom_condition`
+#[derive(Resource)]
+pub struct CustomExitCondition(pub(crate) ExitPredicate);
+
+/// Lets any thread ask the app to exit, like a crash handler or an updater
+///
+/// Clone it out of the world and move it to the thread. The request wakes the event loop up
+/// even while it waits in `UpdateMode::Reactive`, and the app exits cleanly through `AppExit`
+/// in the next frame, see `pu_exit_on_request`.
+///
+/// The default has no event loop to wake up, like when stepping the app with `FrameStepper`.
+#[derive(Resource, Clone, Default)]
+pub struct ExitHandle {
+    requested: Arc<AtomicBool>,
+    proxy: Option<Arc<Mutex<EventLoopProxy<RunnerEvent>>>>,
+}
+
+#[allow(dead_code)]
+impl ExitHandle {
+    pub(crate) fn new(proxy: EventLoopProxy<RunnerEvent>) -> Self {
+        ExitHandle {
+            requested: Arc::new(AtomicBool::new(false)),
+            proxy: Some(Arc::new(Mutex::new(proxy))),
+        }
+    }
+
+    /// Asks the app to exit
+    /// Does nothing if the event loop has already stopped
+    pub fn request_exit(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+        let Some(proxy) = &self.proxy else {
+            return;
+        };
+        let proxy = proxy
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if proxy.send_event(RunnerEvent::Wake).is_err() {
+            debug!(target: LOG_TARGET, "Exit requested after the event loop stopped");
+        }
+    }
+
+    /// Whether an exit was requested since the last call
+    pub(crate) fn take_request(&self) -> bool {
+        self.requested.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Where `Window::initial_cursor_position` puts the cursor, in physical pixels
+/// Converted with the scale factor of the window, which may be overridden
+pub(crate) fn initial_cursor_warp(window: &Window) -> Option<PhysicalPosition<f64>> {
+    let position = window.initial_cursor_position?;
+    let scale_factor = window.resolution.scale_factor();
+    Some(PhysicalPosition::new(
+        position.x as f64 * scale_factor,
+        position.y as f64 * scale_factor,
+    ))
+}
+
+/// How many times grabbing the cursor failed in a row for each window
+///
+/// A window with an entry tries again next frame, until `CURSOR_GRAB_ATTEMPTS` is reached.
+#[derive(Default)]
+pub(crate) struct CursorGrabAttempts(HashMap<Entity, u32>);
+
+impl CursorGrabAttempts {
+    /// Records a failed grab, returns whether it is tried again next frame
+    /// Once the last attempt failed the window is forgotten, so the caller can give up.
+    pub(crate) fn failed(&mut self, entity: Entity) -> bool {
+        let attempts = self.0.entry(entity).or_insert(0);
+        *attempts += 1;
+        if *attempts < CURSOR_GRAB_ATTEMPTS {
+            return true;
+        }
+        self.0.remove(&entity);
+        false
+    }
+
+    /// Applies a cursor grab mode with `try_grab`, see `WinitWindows::grab_cursor`
+    /// Returns whether the grab is in effect
+    pub(crate) fn grab(
+        &mut self,
+        entity: Entity,
+        mode: CursorGrabMode,
+        mut try_grab: impl FnMut(CursorGrabMode) -> Result<(), ExternalError>,
+    ) -> bool {
+        let result = match try_grab(mode) {
+            Err(ExternalError::NotSupported(_)) if mode == CursorGrabMode::Locked => {
+                debug!(target: LOG_TARGET,
+                    "Locking the cursor is not supported, confining it to {:?} instead",
+                    entity
+                );
+                try_grab(CursorGrabMode::Confined)
+            }
+            result => result,
+        };
+
+        match result {
+            Ok(()) => {
+                self.reset(entity);
+                mode != CursorGrabMode::None
+            }
+            Err(err) if mode == CursorGrabMode::None => {
+                warn!(target: LOG_TARGET, "Could not release the cursor of {:?}: {err}", entity);
+                false
+            }
+            Err(err) => {
+                if self.failed(entity) {
+                    debug!(target: LOG_TARGET,
+                        "Could not grab the cursor on {:?}, trying again next frame: {err}",
+                        entity
+                    );
+                    return false;
+                }
+
+                if mode == CursorGrabMode::Locked && try_grab(CursorGrabMode::Confined).is_ok() {
+                    warn!(target: LOG_TARGET,
+                        "Could not lock the cursor on {:?}, confined it instead: {err}",
+                        entity
+                    );
+                    return true;
+                }
+                warn!(target: LOG_TARGET, "Could not grab the cursor on {:?}: {err}", entity);
+                false
+            }
+        }
+    }
+
+    /// Forgets the failures of a window, after a grab worked or the mode changed
+    pub(crate) fn reset(&mut self, entity: Entity) {
+        self.0.remove(&entity);
+    }
+
+    pub(crate) fn retries(&self, entity: Entity) -> bool {
+        self.0.contains_key(&entity)
+    }
+}
+
+/// Activation tokens on their way to the windows they are meant for
+///
+/// The launcher passes one at startup, which the first window that is created takes. Tokens
+/// requested for a window, like by `u_focus_window`, can only be used by that window once its
+/// native window is built again, since winit can't activate an existing window with them.
+#[cfg(target_os = "linux")]
+pub(crate) struct ActivationTokens<S, T> {
+    pub(crate) launcher: Option<T>,
+    requested: Vec<(S, Entity)>,
+    delivered: HashMap<Entity, T>,
+}
+
+#[cfg(target_os = "linux")]
+impl<S, T> Default for ActivationTokens<S, T> {
+    fn default() -> Self {
+        ActivationTokens {
+            launcher: None,
+            requested: Vec::new(),
+            delivered: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl<S: PartialEq, T> ActivationTokens<S, T> {
+    pub(crate) fn request(&mut self, serial: S, entity: Entity) {
+        self.requested.push((serial, entity));
+    }
+
+    /// Keeps a delivered token for the window it was requested for
+    /// Returns that window, `None` if nothing requested the token
+    pub(crate) fn deliver(&mut self, serial: S, token: T) -> Option<Entity> {
+        let index = self
+            .requested
+            .iter()
+            .position(|(requested, _)| *requested == serial)?;
+        let (_, entity) = self.requested.swap_remove(index);
+        self.delivered.insert(entity, token);
+        Some(entity)
+    }
+
+    /// The token to create the window of an entity with
+    pub(crate) fn take(&mut self, entity: Entity) -> Option<T> {
+        self.delivered
+            .remove(&entity)
+            .or_else(|| self.launcher.take())
+    }
+
+    /// Drops the tokens of windows that are gone for good
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(Entity) -> bool) {
+        self.requested.retain(|&(_, entity)| keep(entity));
+        self.delivered.retain(|&entity, _| keep(entity));
+    }
+}
+
+/// Pauses the app, skipping every update until it is resumed, e.g. while a modal native dialog
+/// is open or to freeze the app while debugging
+///
+/// This is a hard pause, unlike `UpdateMode::Reactive` which only updates less often. Window
+/// events are still handled and the `Paused` schedule runs instead of the main schedule, so
+/// windows can still be closed and the app can exit. Since systems don't run while paused, clone
+/// this out of the world and resume from the code that paused, like the callback of the dialog.
+///
+/// The default has no event loop to wake up, like when stepping the app with `FrameStepper`.
+#[derive(Resource, Clone, Default)]
+pub struct LoopControl {
+    paused: Arc<AtomicBool>,
+    proxy: Option<Arc<Mutex<EventLoopProxy<RunnerEvent>>>>,
+}
+
+#[allow(dead_code)]
+impl LoopControl {
+    pub(crate) fn new(proxy: EventLoopProxy<RunnerEvent>) -> Self {
+        LoopControl {
+            paused: Arc::new(AtomicBool::new(false)),
+            proxy: Some(Arc::new(Mutex::new(proxy))),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Skips updates starting with the next frame
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Runs updates again, waking the event loop up if it is waiting
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+        let Some(proxy) = &self.proxy else {
+            return;
+        };
+        let proxy = proxy
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let _ = proxy.send_event(RunnerEvent::Wake);
+    }
+}
+
+/// The winit window of the primary window, e.g. to create a render surface for it
+///
+/// Saves looking up `PrimaryWindowEntity` in `WinitWindows` by hand. Like `WinitWindows` it
+/// only works in systems running on the main thread.
+#[derive(SystemParam)]
+pub struct PrimaryWinitWindow<'w> {
+    winit_windows: NonSend<'w, WinitWindows>,
+    primary: Res<'w, PrimaryWindowEntity>,
+}
+
+#[allow(dead_code)]
+impl PrimaryWinitWindow<'_> {
+    /// `None` while there is no primary window or it hasn't been created yet
+    pub fn get_primary_window(&self) -> Option<&winit::window::Window> {
+        self.winit_windows.primary_window(&self.primary)
+    }
+}
 
 /// Contains a map from the entity to the window and vice versa
+///
+/// This is a non-send resource because winit only allows windows to be created and used on the
+/// thread running the event loop, which is the main thread on most platforms.
 pub struct WinitWindows {
-    pub windows: HashMap<winit::window::WindowId, winit::window::Window>,
-    pub entity_to_window: HashMap<Entity, winit::window::WindowId>,
-    pub window_to_entity: HashMap<winit::window::WindowId, Entity>,
+    // Only changed together by `insert_window` and `remove_window`, read them through
+    // `get_window`, `entity_for_window_id` and `window_id_for_entity`
+    windows: HashMap<winit::window::WindowId, winit::window::Window>,
+    entity_to_window: HashMap<Entity, winit::window::WindowId>,
+    window_to_entity: HashMap<winit::window::WindowId, Entity>,
+    /// The last logical cursor position inside each window
+    /// Removed when the cursor leaves the window, so the next move starts from zero delta
+    pub cursor_positions: HashMap<Entity, Vec2>,
+    /// How many times grabbing the cursor failed for windows that will try again next frame
+    pub(crate) cursor_grab_attempts: CursorGrabAttempts,
+    /// Where to move the cursor once each window is shown, see `Window::initial_cursor_position`
+    pub(crate) cursor_warps: HashMap<Entity, PhysicalPosition<f64>>,
+    /// Windows `u_focus_window` asked to take focus that didn't report it yet
+    pub(crate) focus_requests: Vec<Entity>,
+    /// The region each window confines the cursor to, see `SetCursorConfineRegionEvent`
+    /// Windows drops the clip when the focus moves away and it doesn't follow the window, so it
+    /// is applied again whenever the window gains focus, moves or is resized
+    #[cfg(windows)]
+    pub(crate) cursor_confine_regions: HashMap<Entity, Rect>,
+    /// Lets windows take focus on Wayland and X11
+    #[cfg(target_os = "linux")]
+    pub(crate) activation_tokens: ActivationTokens<AsyncRequestSerial, ActivationToken>,
+    /// The thread this was created on, alongside the event loop in `WindowPlugin::build`
+    event_loop_thread: ThreadId,
     _not_send_sync: PhantomData<*const ()>,
 }
 
@@ -25,38 +1473,892 @@ impl Default for WinitWindows {
             windows: HashMap::new(),
             entity_to_window: HashMap::new(),
             window_to_entity: HashMap::new(),
+            cursor_positions: HashMap::new(),
+            cursor_grab_attempts: CursorGrabAttempts::default(),
+            cursor_warps: HashMap::new(),
+            focus_requests: Vec::new(),
+            #[cfg(windows)]
+            cursor_confine_regions: HashMap::new(),
+            #[cfg(target_os = "linux")]
+            activation_tokens: ActivationTokens::default(),
+            event_loop_thread: thread::current().id(),
             _not_send_sync: PhantomData,
         }
     }
 }
 
 impl WinitWindows {
+    /// Drops the activation tokens of windows that were destroyed in the meantime
+    #[cfg(target_os = "linux")]
+    pub(crate) fn drop_stale_activation_tokens(&mut self) {
+        let entity_to_window = &self.entity_to_window;
+        self.activation_tokens
+            .retain(|entity| entity_to_window.contains_key(&entity));
+    }
+
+    /// Waits for the window to report focus, see `take_unanswered_focus_requests`
+    pub(crate) fn request_focus(&mut self, entity: Entity) {
+        if !self.focus_requests.contains(&entity) {
+            self.focus_requests.push(entity);
+        }
+    }
+
+    /// Called for every `Focused` event, gaining focus answers the request of the window
+    pub(crate) fn focus_changed(&mut self, entity: Entity, focused: bool) {
+        if focused {
+            self.focus_requests.retain(|&requested| requested != entity);
+        }
+    }
+
+    /// The focus requests made since the last call that no `Focused` event answered,
+    /// the OS didn't let those windows take focus
+    pub(crate) fn take_unanswered_focus_requests(&mut self) -> Vec<Entity> {
+        std::mem::take(&mut self.focus_requests)
+    }
+
+    /// Applies a cursor grab mode to the window of an entity
+    /// Returns whether the grab is in effect, which is never the case for `CursorGrabMode::None`
+    ///
+    /// Platforms without `Locked` confine the cursor instead. Other errors may be temporary,
+    /// the grab is marked to be tried again in the next frames, see `retries_cursor_grab`.
+    /// A lock that keeps failing falls back to confining the cursor.
+    pub(crate) fn grab_cursor(&mut self, entity: Entity, mode: CursorGrabMode) -> bool {
+        // Borrowed through the fields, so the attempts can be updated alongside
+        let Some(winit_window) = self
+            .entity_to_window
+            .get(&entity)
+            .and_then(|window_id| self.windows.get(window_id))
+        else {
+            return false;
+        };
+
+        self.cursor_grab_attempts
+            .grab(entity, mode, |mode| winit_window.set_cursor_grab(mode))
+    }
+
+    /// Whether a failed cursor grab of the window is tried again next frame
+    pub(crate) fn retries_cursor_grab(&self, entity: Entity) -> bool {
+        self.cursor_grab_attempts.retries(entity)
+    }
+
+    /// Adds a window to every map at once, so they can't get out of sync
+    fn insert_window(&mut self, entity: Entity, winit_window: winit::window::Window) {
+        let window_id = winit_window.id();
+        self.entity_to_window.insert(entity, window_id);
+        self.window_to_entity.insert(window_id, entity);
+        self.windows.insert(window_id, winit_window);
+        self.debug_check_maps();
+    }
+
+    /// Moves the cursor to where it was waiting to be put in the window of an entity
+    fn apply_cursor_warp(&mut self, entity: Entity) {
+        let Some(position) = self.cursor_warps.remove(&entity) else {
+            return;
+        };
+        let Some(winit_window) = self.get_window(entity) else {
+            return;
+        };
+        if let Err(err) = winit_window.set_cursor_position(position) {
+            debug!(target: LOG_TARGET, "Could not move the cursor into {:?}: {err}", entity);
+        }
+    }
+
+    /// Removes a window from every map at once, so they can't get out of sync
+    fn remove_window(&mut self, entity: Entity) -> Option<winit::window::Window> {
+        let window_id = self.entity_to_window.remove(&entity)?;
+        self.window_to_entity.remove(&window_id);
+        self.cursor_positions.remove(&entity);
+        self.cursor_warps.remove(&entity);
+        self.cursor_grab_attempts.reset(entity);
+        #[cfg(windows)]
+        self.cursor_confine_regions.remove(&entity);
+        let winit_window = self.windows.remove(&window_id);
+        self.debug_check_maps();
+        winit_window
+    }
+
+    /// Clips the cursor to the confine region of a window, in screen coordinates at the current
+    /// position and scale factor of the window
+    /// Nothing happens while the window is not focused, since the clip applies to the whole screen
+    #[cfg(windows)]
+    pub(crate) fn apply_cursor_confine_region(&self, entity: Entity) {
+        let (Some(rect), Some(winit_window)) = (
+            self.cursor_confine_regions.get(&entity),
+            self.get_window(entity),
+        ) else {
+            return;
+        };
+        if !winit_window.has_focus() {
+            return;
+        }
+        let Ok(origin) = winit_window.inner_position() else {
+            return;
+        };
+
+        let scale_factor = winit_window.scale_factor() as f32;
+        let min = (rect.min * scale_factor).round().as_ivec2();
+        let max = (rect.max * scale_factor).round().as_ivec2();
+        let clip_rect = windows_sys::Win32::Foundation::RECT {
+            left: origin.x + min.x,
+            top: origin.y + min.y,
+            right: origin.x + max.x,
+            bottom: origin.y + max.y,
+        };
+        // SAFETY: the rectangle lives until the end of the call
+        unsafe {
+            windows_sys::Win32::UI::WindowsAndMessaging::ClipCursor(&clip_rect);
+        }
+    }
+
+    /// Makes sure every window is in all maps and both directions point at each other
+    fn debug_check_maps(&self) {
+        debug_assert_eq!(self.entity_to_window.len(), self.window_to_entity.len());
+        debug_assert_eq!(self.entity_to_window.len(), self.windows.len());
+        debug_assert!(self.entity_to_window.iter().all(|(entity, window_id)| {
+            self.window_to_entity.get(window_id) == Some(entity)
+                && self.windows.contains_key(window_id)
+        }));
+    }
+
+    /// The entity of a winit window
+    ///
+    /// Valid from the moment the window is created (`WindowCreatedEvent`) until it is destroyed
+    /// (`WindowDestroyedEvent`). Window ids may be reused by the platform after that,
+    /// so don't keep them around for destroyed windows.
+    pub fn entity_for_window_id(&self, window_id: winit::window::WindowId) -> Option<Entity> {
+        self.window_to_entity.get(&window_id).copied()
+    }
+
+    /// The winit window id of an entity
+    ///
+    /// Valid from the moment the window is created (`WindowCreatedEvent`) until it is destroyed
+    /// (`WindowDestroyedEvent`). Entities are reused by bevy after they are despawned.
+    #[allow(dead_code)]
+    pub fn window_id_for_entity(&self, entity: Entity) -> Option<winit::window::WindowId> {
+        self.entity_to_window.get(&entity).copied()
+    }
+
+    /// Gets the winit window of an entity, if it has been created
+    pub fn get_window(&self, entity: Entity) -> Option<&winit::window::Window> {
+        self.windows.get(self.entity_to_window.get(&entity)?)
+    }
+
+    /// Tells the platform a frame for the window is about to be presented
+    ///
+    /// A renderer should call this right before presenting the surface of the window, after all
+    /// drawing is submitted, so platforms like Wayland can pace frames to the display.
+    /// Returns `None` if the window doesn't exist.
+    #[allow(dead_code)]
+    pub fn pre_present_notify(&self, entity: Entity) -> Option<()> {
+        self.get_window(entity)?.pre_present_notify();
+        Some(())
+    }
+
+    /// Gets the winit window of the primary window, if there is one and it has been created
+    #[allow(dead_code)]
+    pub fn primary_window(&self, primary: &PrimaryWindowEntity) -> Option<&winit::window::Window> {
+        self.get_window(primary.get()?)
+    }
+
+    /// The window containing a point on the desktop in physical pixels, including decorations
+    ///
+    /// This is best effort: winit doesn't tell the stacking order of windows, so if several
+    /// overlap the point, the focused one wins, otherwise the one with the highest entity.
+    /// Windows whose position is unknown (like on Wayland) are never found.
+    #[allow(dead_code)]
+    pub fn window_at_point(&self, point: IVec2) -> Option<Entity> {
+        let windows = self
+            .entity_to_window
+            .iter()
+            .filter_map(|(entity, window_id)| {
+                let winit_window = &self.windows[window_id];
+                let position = winit_window.outer_position().ok()?;
+                let size = winit_window.outer_size();
+                Some(WindowBounds {
+                    entity: *entity,
+                    position: IVec2::new(position.x, position.y),
+                    size: UVec2::new(size.width, size.height),
+                    focused: winit_window.has_focus(),
+                })
+            });
+        topmost_window_at(point, windows)
+    }
+
+    /// The safe area insets of a window, derived from the platform
+    /// Only Android reports them, every other platform returns zero insets
+    pub fn safe_insets(&self, entity: Entity) -> Option<SafeInsets> {
+        #[cfg(target_os = "android")]
+        {
+            use winit::platform::android::WindowExtAndroid;
+
+            let winit_window = self.get_window(entity)?;
+
+            // The content rect is the part of the window not covered by system decorations
+            let content_rect = winit_window.content_rect();
+            let size = winit_window.inner_size();
+            let scale_factor = winit_window.scale_factor() as f32;
+            Some(SafeInsets {
+                top: content_rect.top.max(0) as f32 / scale_factor,
+                bottom: (size.height as i32 - content_rect.bottom).max(0) as f32 / scale_factor,
+                left: content_rect.left.max(0) as f32 / scale_factor,
+                right: (size.width as i32 - content_rect.right).max(0) as f32 / scale_factor,
+            })
+        }
+
+        #[cfg(not(target_os = "android"))]
+        {
+            self.get_window(entity).map(|_| SafeInsets::default())
+        }
+    }
+}
+
+/// Where a window is on the desktop, for `WinitWindows::window_at_point`
+struct WindowBounds {
+    entity: Entity,
+    /// The top left corner including decorations, in physical pixels
+    position: IVec2,
+    /// The outer size in physical pixels
+    size: UVec2,
+    focused: bool,
+}
+
+/// The window containing a point, the focused one or else the highest entity if several do
+fn topmost_window_at(point: IVec2, windows: impl Iterator<Item = WindowBounds>) -> Option<Entity> {
+    windows
+        .filter(|window| {
+            let max = window.position + window.size.as_ivec2();
+            point.cmpge(window.position).all() && point.cmplt(max).all()
+        })
+        .max_by_key(|window| (window.focused, window.entity))
+        .map(|window| window.entity)
+}
+
+/// Fails unless called on the thread running the event loop, usually the main thread
+fn ensure_event_loop_thread(event_loop_thread: ThreadId) -> Result<(), WindowError> {
+    let current = thread::current().id();
+    if current != event_loop_thread {
+        return Err(WindowError::WrongThread(current));
+    }
+    Ok(())
+}
+
+impl WindowBackend for WinitWindows {
+    type Target = winit::event_loop::EventLoopWindowTarget<RunnerEvent>;
+
     /// Only called from a system to open any windows based on their Window component
-    pub fn create_window(
+    fn create_window(
         &mut self,
-        event_loop: &winit::event_loop::EventLoopWindowTarget<()>,
+        event_loop: &winit::event_loop::EventLoopWindowTarget<RunnerEvent>,
         entity: Entity,
-        window: &Window,
-    ) {
-        info!("Opening window {} on {:?}", window.title, entity);
+        window: &mut Window,
+    ) -> Result<(), WindowError> {
+        // winit panics deep inside platform code when this happens, so fail with a clear error
+        // instead. Custom runners must not create windows from other threads
+        ensure_event_loop_thread(self.event_loop_thread)?;
+
+        info!(target: LOG_TARGET, "Opening window {} on {:?}", window.title, entity);
         let mut window_builder = WindowBuilder::new()
-            .with_inner_size(LogicalSize::new(window.width, window.height))
-            .with_title(&window.title);
-        if let Some(icon_data) = window.icon_data.clone() {
-            window_builder = window_builder.with_window_icon(Some(
-                Icon::from_rgba(icon_data, window.icon_width, window.icon_height)
-                    .expect("Bad Icon"),
+            .with_inner_size(LogicalSize::new(
+                window.resolution.width,
+                window.resolution.height,
+            ))
+            .with_title(sanitize_title(&window.title))
+            .with_decorations(window.decorations.unwrap_or(true))
+            .with_transparent(window.transparent)
+            .with_window_level(window.window_level)
+            .with_theme(window.preferred_theme)
+            .with_visible(window.visible)
+            .with_enabled_buttons(window.enabled_buttons)
+            .with_active(window.active)
+            .with_resizable(window.resizable)
+            .with_maximized(window.maximized);
+        // Only known for windows that existed before, like when they are recreated
+        if let Some(position) = window.position {
+            window_builder =
+                window_builder.with_position(PhysicalPosition::new(position.x, position.y));
+        }
+        if window.mode != WindowMode::Windowed {
+            let handles: Vec<_> = event_loop.available_monitors().collect();
+            let primary = event_loop.primary_monitor();
+            window_builder = window_builder.with_fullscreen(winit_fullscreen(
+                window.mode,
+                &handles,
+                primary.as_ref(),
+                None,
             ));
         }
-        let winit_window = window_builder.build(event_loop).unwrap();
-        self.entity_to_window.insert(entity, winit_window.id());
-        self.window_to_entity.insert(winit_window.id(), entity);
-        self.windows.insert(winit_window.id(), winit_window);
+        #[cfg(windows)]
+        {
+            use winit::platform::windows::WindowBuilderExtWindows;
+            window_builder = window_builder.with_skip_taskbar(window.skip_taskbar);
+        }
+        #[cfg(target_os = "macos")]
+        if let Some(tabbing_identifier) = &window.tabbing_identifier {
+            use winit::platform::macos::WindowBuilderExtMacOS;
+            window_builder = window_builder.with_tabbing_identifier(tabbing_identifier);
+        }
+        if let Some(workspace) = window.workspace {
+            debug!(target: LOG_TARGET,
+                "Opening {:?} on workspace {workspace} is not supported on this platform",
+                entity
+            );
+        }
+        if let Some(min_size) = window.resize_constraints.min_size() {
+            window_builder = window_builder.with_min_inner_size(min_size);
+        }
+        if let Some(max_size) = window.resize_constraints.max_size() {
+            window_builder = window_builder.with_max_inner_size(max_size);
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(activation_token) = self.activation_tokens.take(entity) {
+            use winit::platform::startup_notify::WindowBuilderExtStartupNotify;
+            window_builder = window_builder.with_activation_token(activation_token);
+        }
+        if let Some(parent_window) = window.parent_window {
+            // SAFETY: the handle was promised to be valid when the `ParentWindowHandle` was made
+            window_builder =
+                unsafe { window_builder.with_parent_window(Some(parent_window.raw())) };
+        }
+        let icons = window_icons(window);
+        #[cfg(windows)]
+        {
+            use winit::platform::windows::WindowBuilderExtWindows;
+            window_builder = window_builder
+                .with_window_icon(winit_icon(closest_icon(&icons, TITLE_BAR_ICON_SIZE)))
+                .with_taskbar_icon(winit_icon(closest_icon(&icons, u32::MAX)));
+        }
+        #[cfg(not(windows))]
+        {
+            window_builder =
+                window_builder.with_window_icon(winit_icon(closest_icon(&icons, u32::MAX)));
+        }
+        let winit_window = window_builder
+            .build(event_loop)
+            .map_err(WindowError::CreationFailed)?;
+        if !window.cursor_hittest {
+            if let Err(err) = winit_window.set_cursor_hittest(false) {
+                warn!(target: LOG_TARGET, "Could not disable cursor hittest on {:?}: {err}", entity);
+            }
+        }
+        winit_window.set_ime_allowed(window.ime_enabled);
+        winit_window.set_ime_purpose(window.ime_purpose);
+        // Windows opening on a HiDPI monitor don't get a `ScaleFactorChanged` event,
+        // so the scale factor has to be read now for physical sizes to be right on the first frame
+        window.resolution.scale_factor = winit_window.scale_factor();
+        // The builder only knows the scale factor of the OS, so apply the override afterwards
+        if window.resolution.scale_factor_override.is_some() {
+            let size = window.resolution.physical_size();
+            let _ = winit_window.request_inner_size(PhysicalSize::new(size.x, size.y));
+        }
+        window.theme = winit_window.theme();
+        window.focused = winit_window.has_focus();
+        self.insert_window(entity, winit_window);
+        // A hidden window can't take the cursor, it is moved there once the window is shown
+        if let Some(position) = initial_cursor_warp(window) {
+            self.cursor_warps.insert(entity, position);
+            if window.visible {
+                self.apply_cursor_warp(entity);
+            }
+        }
+
+        if window.cursor_grab_mode != CursorGrabMode::None {
+            window.cursor_grab_active = self.grab_cursor(entity, window.cursor_grab_mode);
+        }
+
+        window.position = self.outer_position(entity);
+        Ok(())
+    }
+
+    fn destroy_window(&mut self, entity: Entity) -> bool {
+        // Dropping the winit window closes it
+        let destroyed = self.remove_window(entity).is_some();
+        if !destroyed {
+            // The entity can be despawned before its window was ever created
+            debug!(target: LOG_TARGET, "No window to destroy for {:?}", entity);
+        }
+        destroyed
+    }
+
+    fn has_window(&self, entity: Entity) -> bool {
+        self.entity_to_window.contains_key(&entity)
+    }
+
+    fn entity_for_window_id(&self, window_id: winit::window::WindowId) -> Option<Entity> {
+        WinitWindows::entity_for_window_id(self, window_id)
+    }
+
+    fn request_inner_size(&mut self, entity: Entity, size: UVec2) -> bool {
+        let Some(winit_window) = self.get_window(entity) else {
+            return false;
+        };
+        // Platforms that resize right away return the new size, the `Resized` event follows anyway
+        let _ = winit_window.request_inner_size(PhysicalSize::new(size.x, size.y));
+        true
+    }
+
+    fn set_theme(&mut self, entity: Entity, theme: Option<Theme>) -> Option<Theme> {
+        let winit_window = self.get_window(entity)?;
+        winit_window.set_theme(theme);
+        winit_window.theme()
+    }
+
+    fn set_mode(&mut self, entity: Entity, mode: WindowMode) -> Option<f64> {
+        let winit_window = self.get_window(entity)?;
+        let handles: Vec<_> = winit_window.available_monitors().collect();
+        let primary = winit_window.primary_monitor();
+        let current = winit_window.current_monitor();
+        winit_window.set_fullscreen(winit_fullscreen(
+            mode,
+            &handles,
+            primary.as_ref(),
+            current.as_ref(),
+        ));
+        Some(winit_window.scale_factor())
+    }
+
+    /// The `initial_cursor_position` of the window is applied the first time it is shown
+    fn set_visible(&mut self, entity: Entity, visible: bool) {
+        let Some(winit_window) = self.get_window(entity) else {
+            return;
+        };
+        winit_window.set_visible(visible);
+        if visible {
+            self.apply_cursor_warp(entity);
+        }
+    }
+
+    fn set_minimized(&mut self, entity: Entity, minimized: bool) {
+        if let Some(winit_window) = self.get_window(entity) {
+            winit_window.set_minimized(minimized);
+        }
+    }
+
+    fn set_cursor_grab(&mut self, entity: Entity, mode: CursorGrabMode) -> bool {
+        self.grab_cursor(entity, mode)
+    }
+
+    fn retries_cursor_grab(&self, entity: Entity) -> bool {
+        WinitWindows::retries_cursor_grab(self, entity)
+    }
+
+    fn reset_cursor_grab_attempts(&mut self, entity: Entity) {
+        self.cursor_grab_attempts.reset(entity);
+    }
+
+    fn set_resize_constraints(&mut self, entity: Entity, constraints: WindowResizeConstraints) {
+        if let Some(winit_window) = self.get_window(entity) {
+            winit_window.set_min_inner_size(constraints.min_size());
+            winit_window.set_max_inner_size(constraints.max_size());
+        }
+    }
+
+    /// Non-resizable windows are briefly made resizable to restore them, see
+    /// `u_update_window_constraints`
+    fn set_maximized(&mut self, entity: Entity, maximized: bool) {
+        let Some(winit_window) = self.get_window(entity) else {
+            return;
+        };
+        if !maximized && !winit_window.is_resizable() {
+            winit_window.set_resizable(true);
+            winit_window.set_maximized(false);
+            winit_window.set_resizable(false);
+        } else {
+            winit_window.set_maximized(maximized);
+        }
+    }
+
+    fn set_enabled_buttons(&mut self, entity: Entity, buttons: WindowButtons) {
+        if let Some(winit_window) = self.get_window(entity) {
+            winit_window.set_enabled_buttons(buttons);
+        }
     }
 
-    pub fn destroy_window(&mut self, entity: Entity) {
-        let window = self.entity_to_window.remove(&entity).unwrap();
-        self.window_to_entity.remove(&window);
-        self.windows.remove(&window);
+    fn is_maximized(&self, entity: Entity) -> Option<bool> {
+        Some(self.get_window(entity)?.is_maximized())
+    }
+
+    fn current_mode(&self, entity: Entity, current: WindowMode) -> Option<WindowMode> {
+        let fullscreen = self.get_window(entity)?.fullscreen();
+        Some(window_mode_from_winit(fullscreen, current).unwrap_or(current))
+    }
+
+    fn set_resizable(&mut self, entity: Entity, resizable: bool) -> Option<bool> {
+        let winit_window = self.get_window(entity)?;
+        winit_window.set_resizable(resizable);
+        Some(winit_window.is_maximized())
+    }
+
+    fn inner_size(&self, entity: Entity) -> Option<UVec2> {
+        let size = self.get_window(entity)?.inner_size();
+        Some(UVec2::new(size.width, size.height))
+    }
+
+    fn outer_size(&self, entity: Entity) -> Option<UVec2> {
+        let size = self.get_window(entity)?.outer_size();
+        Some(UVec2::new(size.width, size.height))
+    }
+
+    fn outer_position(&self, entity: Entity) -> Option<IVec2> {
+        let winit_window = self.get_window(entity)?;
+        match winit_window.outer_position() {
+            Ok(position) => Some(IVec2::new(position.x, position.y)),
+            Err(err) => {
+                debug!(target: LOG_TARGET, "Could not get the position of {:?}: {err}", entity);
+                None
+            }
+        }
+    }
+
+    fn set_outer_position(&mut self, entity: Entity, position: IVec2) -> Result<(), WindowError> {
+        let winit_window = self
+            .get_window(entity)
+            .ok_or(WindowError::NoSuchWindow(entity))?;
+        let handles: Vec<_> = winit_window.available_monitors().collect();
+        let monitors = Monitors::from_handles(&handles, None);
+        if monitors.monitor_at(position).is_none() {
+            return Err(WindowError::OffScreen(position));
+        }
+
+        winit_window.set_outer_position(PhysicalPosition::new(position.x, position.y));
+        Ok(())
+    }
+
+    fn current_monitor(&self, entity: Entity) -> Option<Monitor> {
+        let winit_window = self.get_window(entity)?;
+        let handle = winit_window
+            .current_monitor()
+            .or_else(|| winit_window.primary_monitor())?;
+        Some(Monitor::from_handle(&handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::window::testing::{two_monitors, TestApp};
+    use bevy_ecs::system::SystemState;
+
+    #[test]
+    fn actions_can_be_bound_rebound_and_conflict() {
+        let (space, w, up) = (
+            InputBinding::Key(KeyCode::Space),
+            InputBinding::Key(KeyCode::KeyW),
+            InputBinding::Key(KeyCode::ArrowUp),
+        );
+        let mut bindings = KeyBindings::default();
+        bindings.bind("jump", space);
+        bindings.bind("jump", InputBinding::Mouse(MouseButton::Right));
+        bindings.bind("jump", space);
+        bindings.bind("forward", w);
+        assert_eq!(
+            bindings.bindings("jump"),
+            [space, InputBinding::Mouse(MouseButton::Right)]
+        );
+        assert!(bindings.conflicts().is_empty());
+
+        // The player moves jump to W, which forward already uses
+        bindings.rebind("jump", [w]);
+        assert_eq!(bindings.bindings("jump"), [w]);
+        assert_eq!(bindings.actions_for(space).count(), 0);
+        assert_eq!(bindings.conflicts(), vec![(w, vec!["forward", "jump"])]);
+
+        bindings.rebind("forward", [up]);
+        assert!(bindings.conflicts().is_empty());
+        bindings.unbind("forward", up);
+        assert!(bindings.bindings("forward").is_empty());
+        bindings.remove("jump");
+        assert!(bindings.bindings("jump").is_empty());
+    }
+
+    #[test]
+    fn cursor_grab_is_retried_until_it_works() {
+        let entity = Entity::from_raw(0);
+        let mut attempts = CursorGrabAttempts::default();
+        assert!(!attempts.retries(entity));
+
+        // Refused once, like on Wayland right after creation, then granted
+        assert!(attempts.failed(entity));
+        assert!(attempts.retries(entity));
+        attempts.reset(entity);
+        assert!(!attempts.retries(entity));
+
+        // A grab that keeps failing is given up after the last attempt
+        for _ in 1..CURSOR_GRAB_ATTEMPTS {
+            assert!(attempts.failed(entity));
+        }
+        assert!(!attempts.failed(entity));
+        assert!(!attempts.retries(entity));
+    }
+
+    #[test]
+    fn specific_video_mode_is_picked_from_the_monitor() {
+        let video_mode = |width, height, refresh_rate_millihertz| VideoMode {
+            size: UVec2::new(width, height),
+            bit_depth: 32,
+            refresh_rate_millihertz,
+        };
+        let mut monitor = two_monitors().monitors.remove(0);
+        monitor.video_modes = vec![
+            video_mode(1280, 720, 60_000),
+            video_mode(1920, 1080, 60_000),
+            video_mode(1920, 1080, 144_000),
+        ];
+
+        let wanted = monitor.video_modes[1];
+        let picked = closest_video_mode(monitor.video_modes.iter(), wanted, |mode| **mode);
+        assert_eq!(picked, Some(&wanted));
+
+        // The monitor changed and no longer supports the chosen mode
+        monitor.video_modes.remove(1);
+        let picked = closest_video_mode(monitor.video_modes.iter(), wanted, |mode| **mode);
+        assert_eq!(picked, Some(&video_mode(1920, 1080, 144_000)));
+    }
+
+    #[test]
+    fn video_modes_are_sorted_without_duplicates() {
+        let video_mode = |width, height, refresh_rate_millihertz, bit_depth| VideoMode {
+            size: UVec2::new(width, height),
+            bit_depth,
+            refresh_rate_millihertz,
+        };
+        // Like a platform that reports the modes of every output connector of the monitor
+        let raw = [
+            video_mode(1920, 1080, 144_000, 32),
+            video_mode(1280, 720, 60_000, 32),
+            video_mode(1920, 1080, 60_000, 32),
+            video_mode(1920, 1080, 144_000, 32),
+            video_mode(1920, 1080, 60_000, 24),
+            video_mode(1280, 720, 60_000, 32),
+        ];
+        let mut monitor = two_monitors().monitors.remove(0);
+        monitor.video_modes = sorted_video_modes(raw.into_iter());
+        assert_eq!(
+            monitor.video_modes,
+            [
+                video_mode(1280, 720, 60_000, 32),
+                video_mode(1920, 1080, 60_000, 24),
+                video_mode(1920, 1080, 60_000, 32),
+                video_mode(1920, 1080, 144_000, 32),
+            ]
+        );
+        assert_eq!(
+            monitor.resolutions(),
+            [
+                (UVec2::new(1280, 720), vec![60_000]),
+                (UVec2::new(1920, 1080), vec![60_000, 144_000]),
+            ]
+        );
+    }
+
+    #[test]
+    fn control_characters_are_removed_from_titles() {
+        assert!(matches!(sanitize_title("Ruxel"), Cow::Borrowed("Ruxel")));
+        assert_eq!(sanitize_title("Level 1\nThe Caves\0"), "Level 1 The Caves");
+        assert_eq!(sanitize_title("\0\u{7}"), "");
+    }
+
+    #[test]
+    fn primary_winit_window_is_none_without_a_native_window() {
+        let has_primary_window = |test: &mut TestApp| {
+            let mut state = SystemState::<PrimaryWinitWindow>::new(test.world());
+            state.get(test.world()).get_primary_window().is_some()
+        };
+
+        let mut test = TestApp::windowless();
+        test.step();
+        assert!(!has_primary_window(&mut test));
+
+        // The mock backend doesn't create winit windows
+        let mut test = TestApp::new(Default::default());
+        test.step();
+        assert!(test
+            .world()
+            .resource::<PrimaryWindowEntity>()
+            .get()
+            .is_some());
+        assert!(!has_primary_window(&mut test));
+    }
+
+    #[test]
+    fn point_is_in_the_window_it_falls_on() {
+        let (left, right) = (Entity::from_raw(0), Entity::from_raw(1));
+        let windows = || {
+            [
+                WindowBounds {
+                    entity: left,
+                    position: IVec2::new(100, 100),
+                    size: UVec2::new(800, 600),
+                    focused: true,
+                },
+                WindowBounds {
+                    entity: right,
+                    position: IVec2::new(1000, 100),
+                    size: UVec2::new(800, 600),
+                    focused: false,
+                },
+            ]
+            .into_iter()
+        };
+
+        assert_eq!(
+            topmost_window_at(IVec2::new(100, 100), windows()),
+            Some(left)
+        );
+        assert_eq!(
+            topmost_window_at(IVec2::new(1500, 400), windows()),
+            Some(right)
+        );
+        // Between the windows, and right past the bottom right corner of the left one
+        assert_eq!(topmost_window_at(IVec2::new(950, 400), windows()), None);
+        assert_eq!(topmost_window_at(IVec2::new(900, 700), windows()), None);
+    }
+
+    #[test]
+    fn icon_sizes_are_picked_for_title_bar_and_taskbar() {
+        let icon = |size: u32| WindowIcon {
+            width: size,
+            height: size,
+            data: vec![0; (size * size * 4) as usize],
+        };
+        let broken = WindowIcon {
+            data: vec![0; 4],
+            ..icon(48)
+        };
+        let window = Window {
+            icon_data: None,
+            icons: vec![icon(16), broken, icon(256)],
+            ..Default::default()
+        };
+
+        let icons = window_icons(&window);
+        assert_eq!(icons.len(), 2);
+        assert_eq!(closest_icon(&icons, TITLE_BAR_ICON_SIZE).unwrap().width, 16);
+        assert_eq!(closest_icon(&icons, u32::MAX).unwrap().width, 256);
+        assert!(winit_icon(closest_icon(&icons, u32::MAX)).is_some());
+    }
+
+    #[test]
+    fn windows_are_only_created_on_the_event_loop_thread() {
+        assert!(ensure_event_loop_thread(thread::current().id()).is_ok());
+        let other = thread::spawn(|| thread::current().id()).join().unwrap();
+        assert!(matches!(
+            ensure_event_loop_thread(other),
+            Err(WindowError::WrongThread(_))
+        ));
+    }
+
+    #[test]
+    fn current_monitor_selection_falls_back_to_primary() {
+        let monitors = two_monitors();
+        assert_eq!(
+            monitors.resolve(MonitorSelection::Current, Some(1)),
+            Some(1)
+        );
+        assert_eq!(monitors.resolve(MonitorSelection::Current, None), Some(0));
+        assert_eq!(monitors.resolve(MonitorSelection::Index(1), None), Some(1));
+        assert_eq!(
+            monitors.resolve(MonitorSelection::Index(2), Some(1)),
+            Some(0)
+        );
+
+        // Wayland has no primary monitor
+        let monitors = Monitors {
+            primary: None,
+            ..two_monitors()
+        };
+        assert_eq!(
+            monitors.resolve(MonitorSelection::Current, Some(1)),
+            Some(1)
+        );
+        assert_eq!(monitors.resolve(MonitorSelection::Current, None), None);
+    }
+
+    /// Needs a display, so it does nothing where there is none, like on most CI machines
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn maps_are_empty_after_create_and_destroy() {
+        use winit::event_loop::EventLoopBuilder;
+        use winit::platform::x11::EventLoopBuilderExtX11;
+
+        let Ok(event_loop) = EventLoopBuilder::<RunnerEvent>::with_user_event()
+            .with_any_thread(true)
+            .build()
+        else {
+            return;
+        };
+        let entity = Entity::from_raw(0);
+        let mut winit_windows = WinitWindows::default();
+        let mut window = Window {
+            visible: false,
+            ..Default::default()
+        };
+        winit_windows
+            .create_window(&event_loop, entity, &mut window)
+            .unwrap();
+        let window_id = winit_windows.window_id_for_entity(entity).unwrap();
+        assert_eq!(winit_windows.entity_for_window_id(window_id), Some(entity));
+        let primary = PrimaryWindowEntity(Some(entity));
+        assert_eq!(
+            winit_windows
+                .primary_window(&primary)
+                .map(|primary| primary.id()),
+            Some(window_id)
+        );
+
+        assert!(winit_windows.destroy_window(entity));
+        assert!(winit_windows.primary_window(&primary).is_none());
+        assert!(!winit_windows.destroy_window(entity));
+        assert!(winit_windows.windows.is_empty());
+        assert!(winit_windows.entity_to_window.is_empty());
+        assert!(winit_windows.window_to_entity.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn activation_tokens_go_to_their_window() {
+        let (first, requested, other) = (
+            Entity::from_raw(0),
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+        );
+        let mut tokens = ActivationTokens::<u32, &str> {
+            launcher: Some("launcher"),
+            ..Default::default()
+        };
+        tokens.request(7, requested);
+        assert_eq!(tokens.deliver(8, "unknown"), None);
+        assert_eq!(tokens.deliver(7, "focus"), Some(requested));
+
+        assert_eq!(tokens.take(first), Some("launcher"));
+        assert_eq!(tokens.take(other), None);
+        assert_eq!(tokens.take(requested), Some("focus"));
+        assert_eq!(tokens.take(requested), None);
+    }
+
+    #[test]
+    fn initial_cursor_warp_is_in_physical_pixels() {
+        let mut window = Window::default();
+        assert_eq!(initial_cursor_warp(&window), None);
+
+        window.initial_cursor_position = Some(Vec2::new(400.0, 300.0));
+        window.resolution.set_scale_factor_override(Some(1.5));
+        assert_eq!(
+            initial_cursor_warp(&window),
+            Some(PhysicalPosition::new(600.0, 450.0))
+        );
+    }
+
+    #[test]
+    fn focus_requests_are_answered_by_focused_events() {
+        let (answered, ignored) = (Entity::from_raw(0), Entity::from_raw(1));
+        let mut winit_windows = WinitWindows::default();
+        winit_windows.request_focus(answered);
+        winit_windows.request_focus(ignored);
+        winit_windows.request_focus(ignored);
+
+        winit_windows.focus_changed(ignored, false);
+        winit_windows.focus_changed(answered, true);
+        assert_eq!(winit_windows.take_unanswered_focus_requests(), [ignored]);
+        assert!(winit_windows.take_unanswered_focus_requests().is_empty());
     }
 }
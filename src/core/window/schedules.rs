@@ -0,0 +1,55 @@
+use bevy_ecs::schedule::{ScheduleLabel, SystemSet};
+
+/// Runs once when the event loop is exiting, after the final update.
+///
+/// Add systems here to flush logs, save state or release resources before the app is dropped.
+///
+/// Teardown happens in this order:
+/// 1. A `WindowClosingEvent` is sent for every remaining window that wasn't announced already
+/// 2. This schedule runs, every native window still exists. Renderers must drop their surfaces
+///    here, reading the closing events like in `WindowCloseSet`
+/// 3. The native windows are destroyed and a `WindowDestroyedEvent` is sent for each
+/// 4. `WinitWindows` is removed from the world, and the event loop is dropped after it
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Shutdown;
+
+/// Runs instead of the main schedule while `LoopControl` pauses the app, or while every window
+/// is minimized if `WindowPlugin::update_when_minimized` is off
+///
+/// `First` still runs before it, so events keep being updated. It only runs `WindowClose` and
+/// checks the exit condition, so the user can still close windows and quit while nothing else
+/// updates.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Paused;
+
+/// Closes windows, see `WindowCloseSet`
+///
+/// Runs during `Update` and in `Paused`, so systems added here (like ones denying close
+/// requests) also run while the app is paused.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WindowClose;
+
+/// The steps of closing windows in `WindowClose`, which run in this order
+///
+/// Every close, whether the user clicked the close button or the app asked for it, starts as a
+/// `WindowCloseRequestedEvent`. Systems that may refuse to close a window (e.g. to ask about
+/// unsaved changes first) read those events in `WindowCloseSet::Veto` and call
+/// `WindowCloseDenied::deny`, every request that wasn't denied is despawned afterwards.
+/// Windows with `Window::confirm_close` set are only despawned after a `ConfirmWindowCloseEvent`.
+///
+/// A renderer holding a surface of a window has to drop it between the two steps:
+/// read `WindowClosingEvent` in a system that runs `.after(WindowCloseSet::Announce)`
+/// and `.before(WindowCloseSet::Destroy)`, otherwise the surface outlives its window.
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WindowCloseSet {
+    /// Turns close requests from the OS into `WindowCloseRequestedEvent`
+    Request,
+    /// Where systems can deny close requests for the current frame
+    Veto,
+    /// Despawns windows with a close request that wasn't denied or that were confirmed
+    Despawn,
+    /// Emits `WindowClosingEvent` for every entity that lost its `Window` component
+    Announce,
+    /// Destroys the native windows announced before and emits `WindowDestroyedEvent`
+    Destroy,
+}
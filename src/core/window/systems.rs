@@ -1,54 +1,1092 @@
-use crate::core::window::components::{PrimaryWindow, Window};
-use crate::core::window::events::CloseRequestedEvent;
-use crate::core::window::resources::{PrimaryWindowCount, WinitWindows};
+use crate::core::window::backend::WindowBackend;
+use crate::core::window::components::{CachedWindow, PrimaryWindow, Window, WindowName};
+use crate::core::window::events::{
+    ActionEvent, CloseRequestedEvent, CloseSecondaryWindowsEvent, ConfirmWindowCloseEvent,
+    DumpWindowDiagnosticsEvent, EnterWindowlessEvent, FocusWindowEvent, KeyboardInputEvent,
+    MoveWindowToMonitorEvent, PrimaryWindowChangedEvent, RecreateWindowEvent,
+    ScaleFactorChangedEvent, SetCursorConfineRegionEvent, SetPrimaryWindowEvent,
+    SetWindowOpacityEvent, WindowCloseRequestedEvent, WindowClosingEvent, WindowCommand,
+    WindowCommandEvent, WindowDestroyedEvent, WindowModeChangedEvent, WindowMovedEvent,
+};
+use crate::core::window::resources::{
+    sanitize_title, Actions, ButtonInput, ClosingWindows, ControlFlowSettings, CustomExitCondition,
+    DeferredPrimaryWindow, ExitHandle, InputBinding, KeyBindings, Monitors, PrimaryWindowEntity,
+    RunnerSettings, ScreensaverInhibitor, WindowCloseDenied, WindowConfig, WindowCounts,
+    WindowDiagnostics, WindowNames, WindowSnapshot, Windowless, Windows, WinitWindows,
+};
+use crate::core::window::schedules::WindowClose;
+use crate::core::window::{UpdateMode, LOG_TARGET};
 use bevy_app::AppExit;
 use bevy_ecs::prelude::*;
-use log::{info, warn};
+use bevy_math::UVec2;
+use log::{debug, info, warn};
+use std::collections::HashSet;
+use winit::dpi::PhysicalSize;
+use winit::event::{ElementState, MouseButton};
+use winit::keyboard::{Key, KeyCode, NamedKey};
+use winit::window::UserAttentionType;
+
+/// Translates the keys and mouse buttons held down into the actions of `KeyBindings`
+/// Runs in `PreUpdate`, so every system in `Update` sees the same `Actions`
+pub fn pr_update_actions(
+    bindings: Res<KeyBindings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut actions: ResMut<Actions>,
+    mut action_events: EventWriter<ActionEvent>,
+) {
+    let actions = &mut *actions;
+    actions.just_pressed.clear();
+    actions.just_released.clear();
+
+    for (action, action_bindings) in bindings.iter() {
+        let (mut pressed, mut tapped) = (false, false);
+        for binding in action_bindings {
+            let (is_pressed, is_just_pressed) = match *binding {
+                InputBinding::Key(key) => (keyboard.pressed(key), keyboard.just_pressed(key)),
+                InputBinding::Mouse(button) => (mouse.pressed(button), mouse.just_pressed(button)),
+            };
+            pressed |= is_pressed;
+            tapped |= is_just_pressed;
+        }
+
+        let was_pressed = actions.pressed.contains(action);
+        if !was_pressed && (pressed || tapped) {
+            actions.just_pressed.insert(action.to_string());
+            action_events.send(ActionEvent {
+                action: action.to_string(),
+                state: ElementState::Pressed,
+            });
+        }
+        if (was_pressed || tapped) && !pressed {
+            actions.just_released.insert(action.to_string());
+            action_events.send(ActionEvent {
+                action: action.to_string(),
+                state: ElementState::Released,
+            });
+        }
+        if pressed {
+            actions.pressed.insert(action.to_string());
+        } else {
+            actions.pressed.remove(action);
+        }
+    }
+
+    // Actions that were removed from the bindings while held down
+    let removed: Vec<String> = actions
+        .pressed
+        .iter()
+        .filter(|action| bindings.bindings(action).is_empty())
+        .cloned()
+        .collect();
+    for action in removed {
+        actions.pressed.remove(&action);
+        actions.just_released.insert(action.clone());
+        action_events.send(ActionEvent {
+            action,
+            state: ElementState::Released,
+        });
+    }
+}
+
+/// Spawns the primary window once startup systems had the chance to configure it
+pub fn ps_spawn_deferred_primary_window(
+    mut commands: Commands,
+    mut deferred_primary_window: ResMut<DeferredPrimaryWindow>,
+) {
+    if let Some(primary_window) = deferred_primary_window.0.take() {
+        commands.spawn((primary_window, PrimaryWindow));
+    }
+    commands.remove_resource::<DeferredPrimaryWindow>();
+}
+
+/// Moves the `PrimaryWindow` component to the window of the last `SetPrimaryWindowEvent`
+/// Runs before `u_primary_window_check`, which then sees the new primary as the only one
+pub fn u_set_primary_window(
+    mut commands: Commands,
+    mut set_primary_window_event: EventReader<SetPrimaryWindowEvent>,
+    windows: Query<Has<PrimaryWindow>, With<Window>>,
+    primary_windows: Query<Entity, With<PrimaryWindow>>,
+) {
+    let Some(entity) = set_primary_window_event
+        .read()
+        .last()
+        .map(|event| event.entity)
+    else {
+        return;
+    };
+
+    match windows.get(entity) {
+        Ok(true) => {}
+        Ok(false) => {
+            info!(target: LOG_TARGET, "Making {:?} the primary window", entity);
+            for primary in primary_windows.iter() {
+                commands.entity(primary).remove::<PrimaryWindow>();
+            }
+            commands.entity(entity).insert(PrimaryWindow);
+        }
+        Err(_) => {
+            warn!(target: LOG_TARGET, "Can't make {:?} the primary window, it has no Window", entity);
+        }
+    }
+}
 
 /// System to make sure there is ever one primary window
 /// It will remove the primary window component from any duplicates found
 pub fn u_primary_window_check(
     mut commands: Commands,
-    mut query: Query<(Entity, Option<&Window>), Added<PrimaryWindow>>,
-    mut primary_window_count: ResMut<PrimaryWindowCount>,
-) {
-    for (entity, window) in query.iter_mut() {
-        primary_window_count.0 += 1;
-        if primary_window_count.0 > 1 {
-            let with_window_titled = if let Some(window) = window {
-                format!("with Window titled \"{}\"", window.title)
-            } else {
-                "with no Window component".to_string()
-            };
-            warn!(
-                "A primary window already exists, removing PrimaryWindow component from entity {:?} {}",
-                entity, with_window_titled
-            );
-            commands.entity(entity).remove::<PrimaryWindow>();
-            primary_window_count.0 -= 1;
+    query: Query<(Entity, Option<&Window>, Ref<PrimaryWindow>)>,
+) {
+    let mut has_primary = query.iter().any(|(_, _, primary)| !primary.is_added());
+    for (entity, window, primary) in query.iter() {
+        if !primary.is_added() {
+            continue;
+        }
+        if !has_primary {
+            has_primary = true;
+            continue;
         }
+
+        let with_window_titled = if let Some(window) = window {
+            format!("with Window titled \"{}\"", window.title)
+        } else {
+            "with no Window component".to_string()
+        };
+        warn!(target: LOG_TARGET,
+            "A primary window already exists, removing PrimaryWindow component from entity {:?} {}",
+            entity, with_window_titled
+        );
+        commands.entity(entity).remove::<PrimaryWindow>();
     }
 }
 
-/// This despawns an entity with a `Window` component when a close requested event is emitted
-pub fn u_despawn_windows(
-    mut commands: Commands,
+/// Keeps `PrimaryWindowEntity` up to date and sends `PrimaryWindowChangedEvent` when it changes
+pub fn u_track_primary_window(
+    primary_window: Query<Entity, (With<Window>, With<PrimaryWindow>)>,
+    mut primary_window_entity: ResMut<PrimaryWindowEntity>,
+    mut primary_window_changed_event: EventWriter<PrimaryWindowChangedEvent>,
+) {
+    let new = primary_window.iter().next();
+    let old = primary_window_entity.0;
+    if new != old {
+        primary_window_entity.0 = new;
+        primary_window_changed_event.send(PrimaryWindowChangedEvent { old, new });
+    }
+}
+
+/// Counts the windows into `WindowCounts`, which is only ever updated here
+pub fn u_count_windows(
+    windows: Query<Has<PrimaryWindow>, With<Window>>,
+    mut window_counts: ResMut<WindowCounts>,
+) {
+    let counts = windows
+        .iter()
+        .fold(WindowCounts::default(), |mut counts, is_primary| {
+            counts.total += 1;
+            counts.primary += is_primary as usize;
+            counts
+        });
+    window_counts.set_if_neq(counts);
+}
+
+/// Forwards close requests from the OS as `WindowCloseRequestedEvent`
+///
+/// Clicking the close button repeatedly can queue several requests for the same window, only the
+/// first one of a frame is forwarded. Requests for windows that are already gone, or whose
+/// `Window` was removed and wait to be destroyed, are dropped.
+pub fn u_forward_close_requests<B: WindowBackend>(
     mut close_requested_event: EventReader<CloseRequestedEvent>,
-    winit_windows: NonSendMut<WinitWindows>,
+    mut window_close_requested_event: EventWriter<WindowCloseRequestedEvent>,
+    backend: NonSend<B>,
+    windows: Query<(), With<Window>>,
 ) {
+    let mut forwarded = HashSet::new();
     for event in close_requested_event.read() {
-        let entity = winit_windows.window_to_entity[&event.window_id];
+        let Some(entity) = backend.entity_for_window_id(event.window_id) else {
+            continue;
+        };
+        if !windows.contains(entity) || !forwarded.insert(entity) {
+            continue;
+        }
+        window_close_requested_event.send(WindowCloseRequestedEvent { entity });
+    }
+}
+
+/// Runs the `WindowClose` schedule, as part of `Update` and of `Paused`
+pub fn u_run_window_close(world: &mut World) {
+    world.run_schedule(WindowClose);
+}
+
+/// Requests the focused window to close when Escape is pressed, see `WindowPlugin::close_on_esc`
+pub fn u_close_on_esc(
+    mut keyboard_input_event: EventReader<KeyboardInputEvent>,
+    mut window_close_requested_event: EventWriter<WindowCloseRequestedEvent>,
+    windows: Query<&Window>,
+) {
+    for event in keyboard_input_event.read() {
+        if event.logical_key != Key::Named(NamedKey::Escape)
+            || event.state != ElementState::Pressed
+            || event.repeat
+        {
+            continue;
+        }
+
+        // Keys go to the focused window, but a press can arrive just after focus moved on
+        if windows
+            .get(event.entity)
+            .is_ok_and(|window| window.focused())
+        {
+            window_close_requested_event.send(WindowCloseRequestedEvent {
+                entity: event.entity,
+            });
+        }
+    }
+}
+
+/// This despawns an entity with a `Window` component when a close request for it wasn't denied
+/// Windows that need confirmation are despawned once it arrives instead
+pub fn u_despawn_windows(
+    mut commands: Commands,
+    mut window_close_requested_event: EventReader<WindowCloseRequestedEvent>,
+    mut confirm_close_event: EventReader<ConfirmWindowCloseEvent>,
+    mut close_denied: ResMut<WindowCloseDenied>,
+    windows: Query<&Window>,
+) {
+    // Several systems can request the same window to close in one frame
+    let mut handled = HashSet::new();
+    for event in window_close_requested_event.read() {
+        if !handled.insert(event.entity) {
+            continue;
+        }
+        if close_denied.is_denied(event.entity) {
+            debug!(target: LOG_TARGET, "Close of {:?} was denied", event.entity);
+            continue;
+        }
+        match windows.get(event.entity) {
+            Ok(window) if window.confirm_close => {
+                debug!(target: LOG_TARGET, "Close of {:?} waits for confirmation", event.entity);
+            }
+            Ok(_) => commands.entity(event.entity).despawn(),
+            Err(_) => {}
+        }
+    }
+    let mut confirmed = HashSet::new();
+    for event in confirm_close_event.read() {
+        if windows.contains(event.entity) && confirmed.insert(event.entity) {
+            commands.entity(event.entity).despawn();
+        }
+    }
+    close_denied.0.clear();
+}
+
+/// Despawns every window and inserts `Windowless` when an `EnterWindowlessEvent` is sent
+/// Runs in `WindowCloseSet::Despawn`, so the windows are announced and destroyed this frame
+pub fn u_enter_windowless(
+    mut commands: Commands,
+    mut enter_windowless_event: EventReader<EnterWindowlessEvent>,
+    windows: Query<Entity, With<Window>>,
+) {
+    if enter_windowless_event.read().count() == 0 {
+        return;
+    }
+
+    info!(target: LOG_TARGET, "Closing all windows to run without them");
+    commands.insert_resource(Windowless);
+    for entity in windows.iter() {
         commands.entity(entity).despawn();
     }
 }
 
-/// This despawns
-pub fn u_close_windows(
-    mut removed_windows: RemovedComponents<Window>,
+/// Requests every window that isn't the primary window to close when a
+/// `CloseSecondaryWindowsEvent` is sent
+pub fn u_close_secondary_windows(
+    mut close_secondary_event: EventReader<CloseSecondaryWindowsEvent>,
+    mut window_close_requested_event: EventWriter<WindowCloseRequestedEvent>,
+    secondary_windows: Query<Entity, (With<Window>, Without<PrimaryWindow>)>,
+) {
+    // Several events in one frame still only need to close the windows once
+    if close_secondary_event.read().count() == 0 {
+        return;
+    }
+
+    for entity in secondary_windows.iter() {
+        window_close_requested_event.send(WindowCloseRequestedEvent { entity });
+    }
+}
+
+/// Focuses windows when a `FocusWindowEvent` is sent
+///
+/// `Window::focused` is set straight away. Whether focusing worked is only known from the
+/// `Focused` event of the OS, which arrives later. If it didn't arrive by the next frame the
+/// platform didn't allow taking focus, and the user's attention is requested instead.
+///
+/// On Wayland and X11 an activation token is requested instead of the user's attention.
+/// winit can't raise an existing window with it, so once it is delivered the attention is
+/// requested and the token is kept until the window is built again, see `RecreateWindowEvent`.
+pub fn u_focus_window(
+    mut focus_window_event: EventReader<FocusWindowEvent>,
+    mut windows: Query<&mut Window>,
     mut winit_windows: NonSendMut<WinitWindows>,
+    mut control_flow: ResMut<ControlFlowSettings>,
+) {
+    for entity in winit_windows.take_unanswered_focus_requests() {
+        let Some(winit_window) = winit_windows.get_window(entity) else {
+            continue;
+        };
+        // The event can still be on its way
+        if winit_window.has_focus() {
+            continue;
+        }
+
+        debug!(target: LOG_TARGET, "{:?} could not take focus, requesting attention", entity);
+        #[cfg(target_os = "linux")]
+        {
+            use winit::platform::startup_notify::WindowExtStartupNotify;
+            if let Ok(serial) = winit_window.request_activation_token() {
+                winit_windows.activation_tokens.request(serial, entity);
+            } else {
+                winit_window.request_user_attention(Some(UserAttentionType::Informational));
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        winit_window.request_user_attention(Some(UserAttentionType::Informational));
+
+        if let Ok(mut window) = windows.get_mut(entity) {
+            window.focused = false;
+        }
+    }
+
+    for event in focus_window_event.read() {
+        let Some(winit_window) = winit_windows.get_window(event.entity) else {
+            warn!(target: LOG_TARGET, "Tried to focus {:?} which has no window", event.entity);
+            continue;
+        };
+
+        // A window that already has focus doesn't get another `Focused` event
+        if !winit_window.has_focus() {
+            winit_window.focus_window();
+            winit_windows.request_focus(event.entity);
+        }
+
+        if let Ok(mut window) = windows.get_mut(event.entity) {
+            window.focused = true;
+        }
+    }
+
+    // The answer is checked in the next frame, which shouldn't wait for other events
+    if !winit_windows.focus_requests.is_empty() {
+        control_flow.next_iteration = Some(UpdateMode::Continuous);
+    }
+}
+
+/// Handles `SetWindowOpacityEvent`
+pub fn u_set_window_opacity(
+    mut set_opacity_event: EventReader<SetWindowOpacityEvent>,
+    winit_windows: NonSend<WinitWindows>,
+) {
+    for event in set_opacity_event.read() {
+        let Some(winit_window) = winit_windows.get_window(event.entity) else {
+            continue;
+        };
+
+        let opacity = event.opacity.clamp(0.0, 1.0);
+        #[cfg(windows)]
+        {
+            use windows_sys::Win32::UI::WindowsAndMessaging::{
+                GetWindowLongPtrW, SetLayeredWindowAttributes, SetWindowLongPtrW, GWL_EXSTYLE,
+                LWA_ALPHA, WS_EX_LAYERED,
+            };
+            use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+            let Ok(RawWindowHandle::Win32(handle)) =
+                winit_window.window_handle().map(|handle| handle.as_raw())
+            else {
+                continue;
+            };
+            let hwnd = handle.hwnd.get();
+            // Only layered windows can be translucent as a whole
+            // SAFETY: the handle belongs to a window that exists until the winit window is dropped
+            let applied = unsafe {
+                let style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE);
+                if style & WS_EX_LAYERED as isize == 0 {
+                    SetWindowLongPtrW(hwnd, GWL_EXSTYLE, style | WS_EX_LAYERED as isize);
+                }
+                SetLayeredWindowAttributes(hwnd, 0, (opacity * 255.0).round() as u8, LWA_ALPHA)
+            };
+            if applied == 0 {
+                warn!(target: LOG_TARGET,
+                    "Could not set the opacity of {:?} to {opacity}", event.entity
+                );
+            }
+        }
+        #[cfg(not(windows))]
+        {
+            // There is nothing to forward this to until winit gets a window opacity API
+            let _ = winit_window;
+            debug!(target: LOG_TARGET,
+                "Setting window opacity to {opacity} on {:?} is not supported on this platform",
+                event.entity
+            );
+        }
+    }
+}
+
+/// The commands of one window in a frame, see `WindowCommand`
+#[derive(Default)]
+struct PendingWindowCommands {
+    title: Option<String>,
+    size: Option<UVec2>,
+    position: Option<WindowCommand>,
+}
+
+/// Applies the `WindowCommandEvent`s of a frame, in the order documented on `WindowCommand`
+pub fn u_apply_window_commands<B: WindowBackend>(
+    mut command_events: EventReader<WindowCommandEvent>,
+    mut windows: Query<(&mut Window, &mut CachedWindow)>,
+    mut backend: NonSendMut<B>,
+) {
+    // Windows are handled in the order their first command was sent
+    let mut pending: Vec<(Entity, PendingWindowCommands)> = Vec::new();
+    for event in command_events.read() {
+        let index = match pending
+            .iter()
+            .position(|(entity, _)| *entity == event.entity)
+        {
+            Some(index) => index,
+            None => {
+                pending.push((event.entity, PendingWindowCommands::default()));
+                pending.len() - 1
+            }
+        };
+        let commands = &mut pending[index].1;
+        match &event.command {
+            WindowCommand::SetTitle(title) => commands.title = Some(title.clone()),
+            WindowCommand::Resize(size) => commands.size = Some(*size),
+            command => commands.position = Some(command.clone()),
+        }
+    }
+
+    for (entity, commands) in pending {
+        let Ok((mut window, mut cache)) = windows.get_mut(entity) else {
+            continue;
+        };
+
+        // `u_update_title` forwards this to winit
+        if let Some(title) = commands.title {
+            window.title = title;
+        }
+
+        if !backend.has_window(entity) {
+            continue;
+        }
+        if let Some(size) = commands.size {
+            window.resolution.width = size.x;
+            window.resolution.height = size.y;
+            // Only the size, a new scale factor override is still applied by
+            // `u_update_scale_factor_override`
+            cache.0.resolution.width = size.x;
+            cache.0.resolution.height = size.y;
+            backend.request_inner_size(entity, window.resolution.physical_size());
+        }
+
+        let position = match commands.position {
+            Some(WindowCommand::SetPosition(position)) => position,
+            Some(WindowCommand::Center) => {
+                let (Some(monitor), Some(inner), Some(outer)) = (
+                    backend.current_monitor(entity),
+                    backend.inner_size(entity),
+                    backend.outer_size(entity),
+                ) else {
+                    continue;
+                };
+                // The resize above may not be applied yet, so add the decorations to the new size
+                let decorations = outer.as_ivec2() - inner.as_ivec2();
+                let size = window.resolution.physical_size().as_ivec2() + decorations;
+                monitor.position + (monitor.size.as_ivec2() - size) / 2
+            }
+            _ => continue,
+        };
+        match backend.set_outer_position(entity, position) {
+            Ok(()) => window.position = Some(position),
+            Err(err) => warn!(target: LOG_TARGET, "Could not move {:?}: {err}", entity),
+        }
+    }
+}
+
+/// Moves windows onto the monitors of `MoveWindowToMonitorEvent`s
+pub fn u_move_windows_to_monitor<B: WindowBackend>(
+    mut move_events: EventReader<MoveWindowToMonitorEvent>,
+    mut windows: Query<&mut Window>,
+    mut backend: NonSendMut<B>,
+    monitors: Res<Monitors>,
+    mut moved_events: EventWriter<WindowMovedEvent>,
+) {
+    for event in move_events.read() {
+        let (Ok(mut window), Some(size)) = (
+            windows.get_mut(event.entity),
+            backend.outer_size(event.entity),
+        ) else {
+            continue;
+        };
+        let position = backend.outer_position(event.entity).unwrap_or_default();
+        let current = monitors.index_at(position);
+        let Some(target) = monitors.resolve(event.monitor, current) else {
+            warn!(target: LOG_TARGET,
+                "Could not move {:?} to monitor {:?}, there is no such monitor",
+                event.entity, event.monitor
+            );
+            continue;
+        };
+
+        let position = monitors.position_on(target, position, size);
+        match backend.set_outer_position(event.entity, position) {
+            Ok(()) => {
+                window.position = Some(position);
+                moved_events.send(WindowMovedEvent {
+                    entity: event.entity,
+                    position,
+                });
+            }
+            Err(err) => warn!(target: LOG_TARGET, "Could not move {:?}: {err}", event.entity),
+        }
+    }
+}
+
+/// Announces that the native window of any entity that lost its `Window` component will be destroyed
+///
+/// Windows spawned and despawned again before `create_windows` ran never got a native window, so
+/// there is nothing to close and nothing is announced.
+pub fn u_announce_closing_windows<B: WindowBackend>(
+    mut removed_windows: RemovedComponents<Window>,
+    backend: NonSend<B>,
+    mut closing_windows: ResMut<ClosingWindows>,
+    mut closing_events: EventWriter<WindowClosingEvent>,
 ) {
     for entity in removed_windows.read() {
-        winit_windows.destroy_window(entity);
+        if !backend.has_window(entity) {
+            debug!(target: LOG_TARGET, "{:?} was removed before its window was created", entity);
+            continue;
+        }
+        closing_windows.announced.push(entity);
+        closing_events.send(WindowClosingEvent { entity });
+    }
+}
+
+/// Announces the native windows to rebuild for `RecreateWindowEvent`, like closing windows
+///
+/// They are destroyed by `u_close_windows` and created again after the frame, see
+/// `create_windows`. Entities without a native window get one like any new window instead.
+pub fn u_announce_recreated_windows<B: WindowBackend>(
+    mut recreate_events: EventReader<RecreateWindowEvent>,
+    windows: Query<(), With<Window>>,
+    backend: NonSend<B>,
+    mut closing_windows: ResMut<ClosingWindows>,
+    mut closing_events: EventWriter<WindowClosingEvent>,
+) {
+    for event in recreate_events.read() {
+        let entity = event.entity;
+        if !windows.contains(entity)
+            || !backend.has_window(entity)
+            || closing_windows.recreating.contains(&entity)
+        {
+            continue;
+        }
+        closing_windows.announced.push(entity);
+        closing_windows.recreating.push(entity);
+        closing_events.send(WindowClosingEvent { entity });
+    }
+}
+
+/// This destroys the native windows announced by `u_announce_closing_windows`
+/// With `WindowPlugin::deferred_destroy` they are destroyed one frame later instead
+pub fn u_close_windows<B: WindowBackend>(
+    mut closing_windows: ResMut<ClosingWindows>,
+    mut backend: NonSendMut<B>,
+    mut windows: ResMut<Windows>,
+    settings: Res<RunnerSettings>,
+    mut control_flow: ResMut<ControlFlowSettings>,
+    mut destroyed_events: EventWriter<WindowDestroyedEvent>,
+) {
+    let closing_windows = &mut *closing_windows;
+    let mut destroyed: Vec<Entity> = closing_windows.deferred.drain(..).collect();
+    if settings.deferred_destroy {
+        closing_windows
+            .deferred
+            .append(&mut closing_windows.announced);
+    } else {
+        destroyed.append(&mut closing_windows.announced);
+    }
+    // Make sure the next frame comes soon even if the loop would wait for events
+    if !closing_windows.deferred.is_empty() {
+        control_flow.next_iteration = Some(UpdateMode::Continuous);
+    }
+
+    for entity in destroyed {
+        windows.0.retain(|&window| window != entity);
+        if backend.destroy_window(entity) {
+            destroyed_events.send(WindowDestroyedEvent { entity });
+        }
+    }
+}
+
+/// Rebuilds `WindowNames` whenever a `WindowName` is added, changed or removed
+pub fn u_index_window_names(
+    names: Query<(Entity, Ref<WindowName>, Ref<Window>)>,
+    mut removed_names: RemovedComponents<WindowName>,
+    mut removed_windows: RemovedComponents<Window>,
+    mut window_names: ResMut<WindowNames>,
+) {
+    let removed = removed_names.read().count() + removed_windows.read().count() > 0;
+    let mut renamed: Vec<Entity> = names
+        .iter()
+        .filter(|(_, name, window)| name.is_changed() || window.is_added())
+        .map(|(entity, _, _)| entity)
+        .collect();
+    if !removed && renamed.is_empty() {
+        return;
+    }
+
+    // Entity indices are reused, so the order windows got their names in is counted instead
+    let window_names = &mut *window_names;
+    window_names
+        .named_at
+        .retain(|&entity, _| names.contains(entity));
+    renamed.sort();
+    for entity in renamed {
+        window_names
+            .named_at
+            .insert(entity, window_names.next_order);
+        window_names.next_order += 1;
+    }
+
+    let mut sorted: Vec<(Entity, &WindowName)> = names
+        .iter()
+        .map(|(entity, name, _)| (entity, name.into_inner()))
+        .collect();
+    sorted.sort_by_key(|(entity, _)| window_names.named_at[entity]);
+    window_names.names.clear();
+    for (entity, name) in sorted {
+        if let Some(&other) = window_names.names.get(&name.0) {
+            warn!(target: LOG_TARGET,
+                "{:?} and {:?} are both named {}, the name refers to {:?}",
+                other, entity, name.0, other
+            );
+            continue;
+        }
+        window_names.names.insert(name.0.clone(), entity);
+    }
+}
+
+/// Applies changes of `Window::cursor_hittest` to the winit window
+pub fn u_update_cursor_hittest(
+    mut query: Query<(Entity, &Window, &mut CachedWindow), Changed<Window>>,
+    winit_windows: NonSend<WinitWindows>,
+) {
+    for (entity, window, mut cache) in query.iter_mut() {
+        if window.cursor_hittest == cache.0.cursor_hittest {
+            continue;
+        }
+
+        if let Some(winit_window) = winit_windows.get_window(entity) {
+            if let Err(err) = winit_window.set_cursor_hittest(window.cursor_hittest) {
+                warn!(target: LOG_TARGET, "Could not set cursor hittest on {:?}: {err}", entity);
+            }
+        }
+        cache.0.cursor_hittest = window.cursor_hittest;
+    }
+}
+
+/// Applies changes of `Window::cursor_grab_mode` to the winit window
+/// Also tries again to grab the cursor of windows where it failed in an earlier frame
+pub fn u_update_cursor_grab<B: WindowBackend>(
+    mut query: Query<(Entity, &mut Window, &mut CachedWindow)>,
+    mut backend: NonSendMut<B>,
+) {
+    for (entity, mut window, mut cache) in query.iter_mut() {
+        if window.cursor_grab_mode != cache.0.cursor_grab_mode {
+            backend.reset_cursor_grab_attempts(entity);
+            cache.0.cursor_grab_mode = window.cursor_grab_mode;
+        } else if !backend.retries_cursor_grab(entity) {
+            continue;
+        }
+
+        let cursor_grab_active = backend.set_cursor_grab(entity, window.cursor_grab_mode);
+        if window.cursor_grab_active != cursor_grab_active {
+            window.cursor_grab_active = cursor_grab_active;
+        }
+    }
+}
+
+/// Handles `SetCursorConfineRegionEvent`
+/// `Window::cursor_grab_active` tells whether the cursor is confined afterwards
+pub fn u_set_cursor_confine_region(
+    mut confine_region_event: EventReader<SetCursorConfineRegionEvent>,
+    mut windows: Query<&mut Window>,
+    mut winit_windows: NonSendMut<WinitWindows>,
+) {
+    for event in confine_region_event.read() {
+        let Ok(mut window) = windows.get_mut(event.entity) else {
+            continue;
+        };
+
+        let cursor_grab_active = match event.rect {
+            None => {
+                #[cfg(windows)]
+                if winit_windows
+                    .cursor_confine_regions
+                    .remove(&event.entity)
+                    .is_some()
+                {
+                    // SAFETY: a null rectangle releases the cursor
+                    unsafe {
+                        windows_sys::Win32::UI::WindowsAndMessaging::ClipCursor(std::ptr::null());
+                    }
+                }
+                winit_windows.grab_cursor(event.entity, window.cursor_grab_mode)
+            }
+            #[cfg(windows)]
+            Some(rect) => {
+                if winit_windows.get_window(event.entity).is_none() {
+                    continue;
+                }
+                winit_windows
+                    .cursor_confine_regions
+                    .insert(event.entity, rect);
+                winit_windows.apply_cursor_confine_region(event.entity);
+                true
+            }
+            #[cfg(not(windows))]
+            Some(rect) => {
+                debug!(target: LOG_TARGET,
+                    "Confining the cursor to {:?} is not supported, confining it to all of {:?}",
+                    rect, event.entity
+                );
+                winit_windows.grab_cursor(event.entity, winit::window::CursorGrabMode::Confined)
+            }
+        };
+        if window.cursor_grab_active != cursor_grab_active {
+            window.cursor_grab_active = cursor_grab_active;
+        }
+    }
+}
+
+/// Keeps the safe area insets of every window up to date
+pub fn u_update_safe_insets(
+    mut query: Query<(Entity, &mut Window)>,
+    winit_windows: NonSend<WinitWindows>,
+) {
+    for (entity, mut window) in query.iter_mut() {
+        let Some(safe_insets) = winit_windows.safe_insets(entity) else {
+            continue;
+        };
+
+        // Only write when the insets change so that `Changed<Window>` isn't triggered every frame
+        if window.safe_insets != safe_insets {
+            window.safe_insets = safe_insets;
+        }
+    }
+}
+
+/// Applies changes of `Window::preferred_theme` to the winit window
+///
+/// When the preferred theme is cleared, the window goes back to following the OS theme and
+/// `Window::theme` is synced straight away instead of waiting for the next OS theme change.
+pub fn u_update_theme<B: WindowBackend>(
+    mut query: Query<(Entity, &mut Window, &mut CachedWindow), Changed<Window>>,
+    mut backend: NonSendMut<B>,
+) {
+    for (entity, mut window, mut cache) in query.iter_mut() {
+        if window.preferred_theme == cache.0.preferred_theme {
+            continue;
+        }
+
+        if backend.has_window(entity) {
+            window.theme = backend.set_theme(entity, window.preferred_theme);
+        }
+        cache.0.preferred_theme = window.preferred_theme;
+    }
+}
+
+/// Emits a `ScaleFactorChangedEvent` when the `ui_scale` of a window changes
+pub fn u_update_ui_scale(
+    mut query: Query<(Entity, &Window, &mut CachedWindow), Changed<Window>>,
+    mut scale_factor_events: EventWriter<ScaleFactorChangedEvent>,
+) {
+    for (entity, window, mut cache) in query.iter_mut() {
+        if window.ui_scale == cache.0.ui_scale {
+            continue;
+        }
+
+        scale_factor_events.send(ScaleFactorChangedEvent {
+            entity,
+            scale_factor: window.scale_factor(),
+            effective_scale_factor: window.effective_scale_factor(),
+        });
+        cache.0.ui_scale = window.ui_scale;
+    }
+}
+
+/// Resizes the winit window when the scale factor override of a window changes, keeping its
+/// logical size
+/// With `WindowPlugin::ignore_dpi_scaling` the override stays at `1.0`
+pub fn u_update_scale_factor_override(
+    mut query: Query<(Entity, &mut Window, &mut CachedWindow), Changed<Window>>,
+    winit_windows: NonSend<WinitWindows>,
+    settings: Res<RunnerSettings>,
+    mut scale_factor_events: EventWriter<ScaleFactorChangedEvent>,
+) {
+    for (entity, mut window, mut cache) in query.iter_mut() {
+        if settings.ignore_dpi_scaling && window.resolution.scale_factor_override != Some(1.0) {
+            warn!(target: LOG_TARGET,
+                "{:?} can't override its scale factor while DPI scaling is ignored",
+                entity
+            );
+            window.resolution.scale_factor_override = Some(1.0);
+        }
+        if window.resolution.scale_factor_override == cache.0.resolution.scale_factor_override {
+            continue;
+        }
+
+        if let Some(winit_window) = winit_windows.get_window(entity) {
+            let size = window.resolution.physical_size();
+            let _ = winit_window.request_inner_size(PhysicalSize::new(size.x, size.y));
+        }
+        let previous_scale_factor = cache.0.scale_factor();
+        cache.0.resolution.scale_factor_override = window.resolution.scale_factor_override;
+        if window.scale_factor() != previous_scale_factor {
+            scale_factor_events.send(ScaleFactorChangedEvent {
+                entity,
+                scale_factor: window.scale_factor(),
+                effective_scale_factor: window.effective_scale_factor(),
+            });
+        }
+    }
+}
+
+/// Applies changes of `Window::ime_enabled` and `Window::ime_purpose` to the winit window
+pub fn u_update_ime(
+    mut query: Query<(Entity, &Window, &mut CachedWindow), Changed<Window>>,
+    winit_windows: NonSend<WinitWindows>,
+) {
+    for (entity, window, mut cache) in query.iter_mut() {
+        let Some(winit_window) = winit_windows.get_window(entity) else {
+            continue;
+        };
+
+        if window.ime_enabled != cache.0.ime_enabled {
+            winit_window.set_ime_allowed(window.ime_enabled);
+            cache.0.ime_enabled = window.ime_enabled;
+        }
+        if window.ime_purpose != cache.0.ime_purpose {
+            winit_window.set_ime_purpose(window.ime_purpose);
+            cache.0.ime_purpose = window.ime_purpose;
+        }
+    }
+}
+
+/// Applies changes of `Window::visible` to the winit window
+pub fn u_update_visibility<B: WindowBackend>(
+    mut query: Query<(Entity, &Window, &mut CachedWindow), Changed<Window>>,
+    mut backend: NonSendMut<B>,
+) {
+    for (entity, window, mut cache) in query.iter_mut() {
+        if window.visible == cache.0.visible {
+            continue;
+        }
+
+        backend.set_visible(entity, window.visible);
+        cache.0.visible = window.visible;
+    }
+}
+
+/// Resizes the native window when the size of a window changes at runtime, like through
+/// `WindowConfig`
+/// Sizes reported by the OS are already in the cache, so they aren't sent back
+pub fn u_update_window_size<B: WindowBackend>(
+    mut query: Query<(Entity, &Window, &mut CachedWindow), Changed<Window>>,
+    mut backend: NonSendMut<B>,
+) {
+    for (entity, window, mut cache) in query.iter_mut() {
+        let size = (window.resolution.width, window.resolution.height);
+        if size == (cache.0.resolution.width, cache.0.resolution.height) {
+            continue;
+        }
+
+        backend.request_inner_size(entity, window.resolution.physical_size());
+        cache.0.resolution.width = window.resolution.width;
+        cache.0.resolution.height = window.resolution.height;
+    }
+}
+
+/// Applies a new or changed `WindowConfig` to the primary window
+/// The window is only touched if the config differs, so reinserting the same one is free
+pub fn u_apply_window_config(
+    config: Res<WindowConfig>,
+    mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Ok(mut window) = primary_window.get_single_mut() else {
+        return;
+    };
+
+    if config.differs_from(&window) {
+        debug!(target: LOG_TARGET, "Applying {:?} to the primary window", *config);
+        config.apply_to(&mut window);
+    }
+}
+
+/// Refreshes `WindowDiagnostics` and logs it when a `DumpWindowDiagnosticsEvent` is sent
+pub fn u_dump_window_diagnostics(
+    mut dump_events: EventReader<DumpWindowDiagnosticsEvent>,
+    windows: Query<(Entity, &Window, Has<PrimaryWindow>)>,
+    winit_windows: NonSend<WinitWindows>,
+    control_flow: Res<ControlFlowSettings>,
+    mut diagnostics: ResMut<WindowDiagnostics>,
+) {
+    if dump_events.read().count() == 0 {
+        return;
+    }
+
+    diagnostics.update_mode = control_flow.update_mode;
+    diagnostics.windows = windows
+        .iter()
+        .map(|(entity, window, primary)| WindowSnapshot {
+            entity,
+            window_id: winit_windows
+                .get_window(entity)
+                .map(|winit_window| winit_window.id().into()),
+            primary,
+            title: window.title.clone(),
+            size: UVec2::new(window.resolution.width, window.resolution.height),
+            scale_factor: window.scale_factor(),
+            mode: window.mode,
+            focused: window.focused(),
+            minimized: window.minimized(),
+        })
+        .collect();
+    info!(target: LOG_TARGET, "{:#?}", *diagnostics);
+}
+
+/// Applies changes of `Window::title` and `Window::decorations` to the winit window
+pub fn u_update_title(
+    mut query: Query<(Entity, &Window, &mut CachedWindow), Changed<Window>>,
+    winit_windows: NonSend<WinitWindows>,
+) {
+    for (entity, window, mut cache) in query.iter_mut() {
+        let Some(winit_window) = winit_windows.get_window(entity) else {
+            continue;
+        };
+
+        if window.title != cache.0.title {
+            winit_window.set_title(&sanitize_title(&window.title));
+            cache.0.title = window.title.clone();
+        }
+        if window.decorations != cache.0.decorations {
+            if let Some(decorations) = window.decorations {
+                winit_window.set_decorations(decorations);
+            }
+            cache.0.decorations = window.decorations;
+        }
+    }
+}
+
+/// Applies changes of `Window::enabled_buttons` to the winit window
+pub fn u_update_enabled_buttons<B: WindowBackend>(
+    mut query: Query<(Entity, &Window, &mut CachedWindow), Changed<Window>>,
+    mut backend: NonSendMut<B>,
+) {
+    for (entity, window, mut cache) in query.iter_mut() {
+        if window.enabled_buttons == cache.0.enabled_buttons {
+            continue;
+        }
+
+        backend.set_enabled_buttons(entity, window.enabled_buttons);
+        cache.0.enabled_buttons = window.enabled_buttons;
+    }
+}
+
+/// Applies changes of `Window::mode` to the winit window
+pub fn u_update_window_mode<B: WindowBackend>(
+    mut query: Query<(Entity, &mut Window, &mut CachedWindow), Changed<Window>>,
+    mut backend: NonSendMut<B>,
+    mut scale_factor_events: EventWriter<ScaleFactorChangedEvent>,
+    mut mode_changed_events: EventWriter<WindowModeChangedEvent>,
+) {
+    for (entity, mut window, mut cache) in query.iter_mut() {
+        if window.mode == cache.0.mode {
+            continue;
+        }
+
+        // Some platforms move the window to a monitor with a different DPI, or change the
+        // DPI itself, as part of the switch. Pick that up now instead of rendering the first
+        // frame with a stale physical size
+        if let Some(scale_factor) = backend.set_mode(entity, window.mode) {
+            if scale_factor != window.resolution.scale_factor {
+                window.resolution.scale_factor = scale_factor;
+                cache.0.resolution.scale_factor = scale_factor;
+                if window.resolution.scale_factor_override.is_none() {
+                    scale_factor_events.send(ScaleFactorChangedEvent {
+                        entity,
+                        scale_factor,
+                        effective_scale_factor: window.effective_scale_factor(),
+                    });
+                }
+            }
+        }
+        cache.0.mode = window.mode;
+        mode_changed_events.send(WindowModeChangedEvent {
+            entity,
+            mode: window.mode,
+        });
+    }
+}
+
+/// Applies runtime changes to `resize_constraints`, `resizable` and `maximized`
+///
+/// Constraints set back to `None` are cleared on the native window.
+///
+/// Platforms that disable the maximize button of non-resizable windows can refuse to restore them
+/// as well, so a locked window is briefly made resizable to restore it. After `resizable`
+/// changes, `maximized` is read back from the native window in case the platform changed it.
+pub fn u_update_window_constraints<B: WindowBackend>(
+    mut query: Query<(Entity, &mut Window, &mut CachedWindow), Changed<Window>>,
+    mut backend: NonSendMut<B>,
+) {
+    for (entity, mut window, mut cache) in query.iter_mut() {
+        let constraints_changed = window.resize_constraints != cache.0.resize_constraints;
+        let resizable_changed = window.resizable != cache.0.resizable;
+        let maximized_changed = window.maximized != cache.0.maximized;
+        if !constraints_changed && !resizable_changed && !maximized_changed {
+            continue;
+        }
+
+        if constraints_changed {
+            backend.set_resize_constraints(entity, window.resize_constraints);
+        }
+        if maximized_changed {
+            backend.set_maximized(entity, window.maximized);
+        }
+        if resizable_changed {
+            if let Some(maximized) = backend.set_resizable(entity, window.resizable) {
+                if window.maximized != maximized {
+                    window.maximized = maximized;
+                }
+            }
+        }
+        cache.0.resize_constraints = window.resize_constraints;
+        cache.0.resizable = window.resizable;
+        cache.0.maximized = window.maximized;
+    }
+}
+
+/// Keeps the display awake while any window wants to inhibit the screensaver
+pub fn u_inhibit_screensaver(
+    windows: Query<&Window>,
+    mut screensaver_inhibitor: ResMut<ScreensaverInhibitor>,
+) {
+    if windows.iter().any(|window| window.inhibit_screensaver) {
+        screensaver_inhibitor.keep_awake();
+    } else {
+        screensaver_inhibitor.allow_sleep();
     }
 }
 
@@ -58,7 +1096,7 @@ pub fn pu_exit_on_primary_closed(
     windows: Query<(), (With<Window>, With<PrimaryWindow>)>,
 ) {
     if windows.is_empty() {
-        info!("Primary window closed, exiting");
+        info!(target: LOG_TARGET, "Primary window closed, exiting");
         app_exit_event.send(AppExit);
     }
 }
@@ -69,7 +1107,637 @@ pub fn pu_exit_on_all_closed(
     windows: Query<(), With<Window>>,
 ) {
     if windows.is_empty() {
-        info!("All windows closed, exiting");
+        info!(target: LOG_TARGET, "All windows closed, exiting");
         app_exit_event.send(AppExit);
     }
 }
+
+/// Exits the app when another thread asked for it through the `ExitHandle`
+pub fn pu_exit_on_request(
+    exit_handle: Option<Res<ExitHandle>>,
+    mut app_exit_event: EventWriter<AppExit>,
+) {
+    if exit_handle.is_some_and(|exit_handle| exit_handle.take_request()) {
+        info!(target: LOG_TARGET, "Exit requested, exiting");
+        app_exit_event.send(AppExit);
+    }
+}
+
+/// Exits the app when the predicate of `ExitCondition::Custom` returns `true`
+pub fn pu_exit_on_custom_condition(world: &mut World) {
+    let predicate = world.resource::<CustomExitCondition>().0.clone();
+    if predicate(world) {
+        info!(target: LOG_TARGET, "Exit condition met, exiting");
+        world.send_event(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::window::backend::MockBackend;
+    use crate::core::window::components::{MonitorSelection, WindowMode};
+    use crate::core::window::resources::CURSOR_GRAB_ATTEMPTS;
+    use crate::core::window::testing::{two_monitors, TestApp};
+    use crate::core::window::{ExitCondition, WindowPlugin};
+    use bevy_math::IVec2;
+    use winit::dpi::LogicalSize;
+    use winit::window::{CursorGrabMode, Theme, WindowButtons};
+
+    #[test]
+    fn cleared_preferred_theme_follows_the_os() {
+        let mut test = TestApp::windowless();
+        test.backend_mut().system_theme = Some(Theme::Dark);
+        let entity = test.spawn_window(Window {
+            preferred_theme: Some(Theme::Light),
+            ..Default::default()
+        });
+        assert_eq!(
+            test.world().get::<Window>(entity).unwrap().theme,
+            Some(Theme::Light)
+        );
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .preferred_theme = None;
+        test.step();
+        assert_eq!(
+            test.world().get::<Window>(entity).unwrap().theme,
+            Some(Theme::Dark)
+        );
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .preferred_theme = Some(Theme::Light);
+        test.step();
+        assert_eq!(
+            test.world().get::<Window>(entity).unwrap().theme,
+            Some(Theme::Light)
+        );
+        assert_eq!(
+            test.backend().windows[&entity].preferred_theme,
+            Some(Theme::Light)
+        );
+    }
+
+    #[test]
+    fn close_secondary_windows_keeps_the_primary() {
+        let mut test = TestApp::new(Default::default());
+        test.step();
+        let primary = test.world().resource::<Windows>().iter().next().unwrap();
+        let secondaries = [
+            test.spawn_window(Window::default()),
+            test.spawn_window(Window::default()),
+        ];
+
+        test.send(CloseSecondaryWindowsEvent);
+        test.send(CloseSecondaryWindowsEvent);
+        test.step();
+        for entity in secondaries {
+            assert!(test.world().get_entity(entity).is_none());
+        }
+        assert!(test.world().get::<Window>(primary).is_some());
+        let destroyed: Vec<_> = test
+            .events::<WindowDestroyedEvent>()
+            .iter()
+            .map(|event| event.entity)
+            .collect();
+        assert_eq!(destroyed.len(), 2);
+        assert!(secondaries.iter().all(|entity| destroyed.contains(entity)));
+        assert_eq!(
+            test.world()
+                .resource::<Windows>()
+                .iter()
+                .collect::<Vec<_>>(),
+            [primary]
+        );
+    }
+
+    #[test]
+    fn mode_switch_picks_up_a_new_scale_factor() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+
+        // Borderless fullscreen on a HiDPI monitor
+        test.backend_mut().scale_factor = Some(2.0);
+        let mode = WindowMode::BorderlessFullscreen(MonitorSelection::Current);
+        test.world().get_mut::<Window>(entity).unwrap().mode = mode;
+        test.step();
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(window.scale_factor(), 2.0);
+        assert_eq!(window.resolution.physical_size(), UVec2::new(1600, 1200));
+        assert_eq!(test.backend().windows[&entity].mode, mode);
+        let scale_factor_events = test.events::<ScaleFactorChangedEvent>();
+        assert_eq!(scale_factor_events.len(), 1);
+        assert_eq!(scale_factor_events[0].scale_factor, 2.0);
+        assert_eq!(test.events::<WindowModeChangedEvent>().len(), 1);
+
+        // Switching back on the same monitor doesn't change it again
+        test.world().get_mut::<Window>(entity).unwrap().mode = WindowMode::Windowed;
+        test.step();
+        assert!(test.events::<ScaleFactorChangedEvent>().is_empty());
+        assert_eq!(test.events::<WindowModeChangedEvent>().len(), 1);
+    }
+
+    #[test]
+    fn locking_a_maximized_window_reads_back_maximized() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window {
+            maximized: true,
+            ..Default::default()
+        });
+
+        // The platform keeps it maximized, it can still be restored afterwards
+        test.world().get_mut::<Window>(entity).unwrap().resizable = false;
+        test.step();
+        assert!(test.world().get::<Window>(entity).unwrap().maximized);
+        test.world().get_mut::<Window>(entity).unwrap().maximized = false;
+        test.step();
+        let native = &test.backend().windows[&entity];
+        assert!(!native.maximized && !native.resizable);
+
+        // The platform restores it when it is locked
+        test.backend_mut().restores_locked_windows = true;
+        let mut window = test.world().get_mut::<Window>(entity).unwrap();
+        window.resizable = true;
+        window.maximized = true;
+        test.step();
+        test.world().get_mut::<Window>(entity).unwrap().resizable = false;
+        test.step();
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert!(!window.maximized);
+        let cache = test.world().get::<CachedWindow>(entity).unwrap();
+        assert!(!cache.0.maximized && !cache.0.resizable);
+    }
+
+    #[test]
+    fn window_counts_follow_spawns_and_despawns() {
+        let mut test = TestApp::new(WindowPlugin {
+            exit_condition: ExitCondition::DontExit,
+            ..Default::default()
+        });
+        test.step();
+        let counts = *test.world().resource::<WindowCounts>();
+        assert_eq!(
+            (counts.total(), counts.primary(), counts.secondary()),
+            (1, 1, 0)
+        );
+
+        let secondaries: Vec<_> = (0..2)
+            .map(|_| test.spawn_window(Window::default()))
+            .collect();
+        test.step();
+        let counts = *test.world().resource::<WindowCounts>();
+        assert_eq!(
+            (counts.total(), counts.primary(), counts.secondary()),
+            (3, 1, 2)
+        );
+
+        let primary = test
+            .world()
+            .resource::<PrimaryWindowEntity>()
+            .get()
+            .unwrap();
+        test.world().despawn(primary);
+        test.world().despawn(secondaries[0]);
+        test.step();
+        let counts = *test.world().resource::<WindowCounts>();
+        assert_eq!(
+            (counts.total(), counts.primary(), counts.secondary()),
+            (1, 0, 1)
+        );
+    }
+
+    #[test]
+    fn primary_window_changes_are_announced() {
+        let mut test = TestApp::new(WindowPlugin {
+            exit_condition: ExitCondition::DontExit,
+            ..Default::default()
+        });
+        test.step();
+        let primary = test.world().resource::<PrimaryWindowEntity>().get();
+        assert!(primary.is_some());
+        let changes = |test: &TestApp| -> Vec<(Option<Entity>, Option<Entity>)> {
+            test.events::<PrimaryWindowChangedEvent>()
+                .iter()
+                .map(|event| (event.old, event.new))
+                .collect()
+        };
+        assert_eq!(changes(&test), [(None, primary)]);
+
+        // Promotion of another window
+        let other = test.spawn_window(Window::default());
+        test.send(SetPrimaryWindowEvent { entity: other });
+        test.step();
+        assert_eq!(changes(&test), [(primary, Some(other))]);
+        assert_eq!(
+            test.world().resource::<PrimaryWindowEntity>().get(),
+            Some(other)
+        );
+        assert!(test
+            .world()
+            .get::<PrimaryWindow>(primary.unwrap())
+            .is_none());
+
+        test.step();
+        assert!(changes(&test).is_empty());
+
+        // Loss of the primary window
+        test.world().despawn(other);
+        test.step();
+        assert_eq!(changes(&test), [(Some(other), None)]);
+        assert_eq!(test.world().resource::<PrimaryWindowEntity>().get(), None);
+    }
+
+    #[test]
+    fn cleared_minimum_size_is_unbounded() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .resize_constraints
+            .min = Some(UVec2::new(640, 480));
+        test.step();
+        let constraints = test.backend().windows[&entity].resize_constraints;
+        assert_eq!(constraints.min_size(), Some(LogicalSize::new(640, 480)));
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .resize_constraints
+            .min = None;
+        test.step();
+        let constraints = test.backend().windows[&entity].resize_constraints;
+        assert_eq!(constraints.min_size(), None);
+        assert_eq!(constraints.max_size(), None);
+    }
+
+    #[test]
+    fn refused_cursor_grab_works_in_the_next_frame() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        test.backend_mut().failing_cursor_grabs = 1;
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .cursor_grab_mode = CursorGrabMode::Locked;
+        test.step();
+        assert!(
+            !test
+                .world()
+                .get::<Window>(entity)
+                .unwrap()
+                .cursor_grab_active
+        );
+        assert!(test.backend().retries_cursor_grab(entity));
+
+        test.step();
+        assert!(
+            test.world()
+                .get::<Window>(entity)
+                .unwrap()
+                .cursor_grab_active
+        );
+        assert!(!test.backend().retries_cursor_grab(entity));
+        assert_eq!(
+            test.backend().windows[&entity].cursor_grab_mode,
+            CursorGrabMode::Locked
+        );
+    }
+
+    #[test]
+    fn cursor_lock_that_keeps_failing_confines_the_cursor() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        test.backend_mut().failing_cursor_grabs = CURSOR_GRAB_ATTEMPTS;
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .cursor_grab_mode = CursorGrabMode::Locked;
+        for _ in 1..CURSOR_GRAB_ATTEMPTS {
+            test.step();
+            assert!(
+                !test
+                    .world()
+                    .get::<Window>(entity)
+                    .unwrap()
+                    .cursor_grab_active
+            );
+        }
+        test.step();
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert!(window.cursor_grab_active);
+        assert_eq!(window.cursor_grab_mode, CursorGrabMode::Locked);
+        assert_eq!(
+            test.backend().windows[&entity].cursor_grab_mode,
+            CursorGrabMode::Confined
+        );
+        assert!(!test.backend().retries_cursor_grab(entity));
+    }
+
+    #[test]
+    fn primary_window_swaps_between_two_windows() {
+        let mut test = TestApp::windowless();
+        let first = test.spawn_window(Window::default());
+        let second = test.spawn_window(Window::default());
+
+        for primary in [first, second, first] {
+            test.send(SetPrimaryWindowEvent { entity: primary });
+            test.step();
+            assert_eq!(
+                test.world().resource::<PrimaryWindowEntity>().get(),
+                Some(primary)
+            );
+            assert_eq!(test.events::<PrimaryWindowChangedEvent>().len(), 1);
+
+            // The check doesn't take it back in the next frame
+            test.step();
+            let secondary = if primary == first { second } else { first };
+            assert!(test.world().get::<PrimaryWindow>(primary).is_some());
+            assert!(test.world().get::<PrimaryWindow>(secondary).is_none());
+            let counts = *test.world().resource::<WindowCounts>();
+            assert_eq!((counts.total(), counts.primary()), (2, 1));
+        }
+    }
+
+    #[test]
+    fn resize_command_keeps_a_new_scale_factor_override() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .resolution
+            .set_scale_factor_override(Some(2.0));
+        test.send(WindowCommandEvent {
+            entity,
+            command: WindowCommand::Resize(UVec2::new(800, 600)),
+        });
+        test.step();
+
+        let scale_factor_changes = test.events::<ScaleFactorChangedEvent>();
+        assert_eq!(scale_factor_changes.len(), 1);
+        assert_eq!(scale_factor_changes[0].scale_factor, 2.0);
+        let cache = test.world().get::<CachedWindow>(entity).unwrap();
+        assert_eq!(cache.0.resolution.scale_factor_override(), Some(2.0));
+        assert_eq!(
+            test.backend().resize_requests.last(),
+            Some(&(entity, UVec2::new(1600, 1200)))
+        );
+    }
+
+    #[test]
+    fn resize_is_applied_before_centering() {
+        let mut test = TestApp::windowless();
+        test.backend_mut().monitors = two_monitors();
+        let entity = test.spawn_window(Window::default());
+
+        // Sent in the opposite of the order they are applied in
+        for command in [
+            WindowCommand::Center,
+            WindowCommand::Resize(UVec2::new(800, 600)),
+        ] {
+            test.send(WindowCommandEvent { entity, command });
+        }
+        test.step();
+
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(
+            (window.resolution.width, window.resolution.height),
+            (800, 600)
+        );
+        assert_eq!(window.position, Some(IVec2::new(560, 240)));
+        assert_eq!(
+            test.backend().resize_requests.last(),
+            Some(&(entity, UVec2::new(800, 600)))
+        );
+        assert_eq!(
+            test.backend().windows[&entity].position,
+            Some(IVec2::new(560, 240))
+        );
+    }
+
+    #[test]
+    fn only_windows_that_ask_for_it_wait_for_confirmation() {
+        let mut test = TestApp::windowless();
+        let tool = test.spawn_window(Window::default());
+        let document = test.spawn_window(Window {
+            confirm_close: true,
+            ..Default::default()
+        });
+
+        test.send(WindowCloseRequestedEvent { entity: tool });
+        test.send(WindowCloseRequestedEvent { entity: document });
+        test.step();
+        assert!(test.world().get_entity(tool).is_none());
+        assert!(test.world().get::<Window>(document).is_some());
+
+        // The user confirmed in the dialog of the app
+        test.send(ConfirmWindowCloseEvent { entity: document });
+        test.step();
+        assert!(test.world().get_entity(document).is_none());
+        assert_eq!(test.backend().destroyed, [tool, document]);
+    }
+
+    #[test]
+    fn maximize_button_can_be_disabled_and_enabled_again() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        assert_eq!(
+            test.backend().windows[&entity].enabled_buttons,
+            WindowButtons::all()
+        );
+
+        let without_maximize = WindowButtons::all() - WindowButtons::MAXIMIZE;
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .enabled_buttons = without_maximize;
+        test.step();
+        assert_eq!(
+            test.backend().windows[&entity].enabled_buttons,
+            without_maximize
+        );
+
+        test.world()
+            .get_mut::<Window>(entity)
+            .unwrap()
+            .enabled_buttons = WindowButtons::all();
+        test.step();
+        assert_eq!(
+            test.backend().windows[&entity].enabled_buttons,
+            WindowButtons::all()
+        );
+    }
+
+    #[test]
+    fn repeated_close_requests_close_the_window_once() {
+        let mut test = TestApp::windowless();
+        let entity = test.spawn_window(Window::default());
+        let window_id = MockBackend::window_id(entity);
+
+        for _ in 0..3 {
+            test.send(CloseRequestedEvent { window_id });
+        }
+        test.step();
+        assert_eq!(test.events::<WindowCloseRequestedEvent>().len(), 1);
+        assert_eq!(test.events::<WindowClosingEvent>().len(), 1);
+        assert!(test.world().get_entity(entity).is_none());
+        assert_eq!(test.backend().destroyed, [entity]);
+
+        // A late request for the despawned window is dropped
+        test.send(CloseRequestedEvent { window_id });
+        test.step();
+        assert!(test.events::<WindowCloseRequestedEvent>().is_empty());
+        assert_eq!(test.backend().destroyed, [entity]);
+    }
+
+    #[test]
+    fn escape_closes_the_focused_window() {
+        let mut test = TestApp::new(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_on_esc: true,
+            ..Default::default()
+        });
+        let unfocused = test.spawn_window(Window::default());
+        let focused = test.spawn_window(Window::default());
+        test.world().get_mut::<Window>(focused).unwrap().focused = true;
+
+        // The key event of the focused window, and a stale one for the other window
+        for entity in [unfocused, focused] {
+            test.send(KeyboardInputEvent {
+                entity,
+                logical_key: Key::Named(NamedKey::Escape),
+                physical_key: winit::keyboard::PhysicalKey::Code(KeyCode::Escape),
+                state: ElementState::Pressed,
+                repeat: false,
+            });
+        }
+        test.step();
+        assert!(test.world().get_entity(focused).is_none());
+        assert!(test.world().get::<Window>(unfocused).is_some());
+    }
+
+    #[test]
+    fn action_stays_pressed_while_any_of_its_keys_is_held() {
+        let mut test = TestApp::windowless();
+        let mut bindings = KeyBindings::default();
+        bindings.bind("jump", InputBinding::Key(KeyCode::Space));
+        bindings.bind("jump", InputBinding::Key(KeyCode::KeyW));
+        test.world().insert_resource(bindings);
+        let set_key = |test: &mut TestApp, key, pressed| {
+            let mut keyboard = test.world().resource_mut::<ButtonInput<KeyCode>>();
+            keyboard.clear_just();
+            if pressed {
+                keyboard.press(key);
+            } else {
+                keyboard.release(key);
+            }
+            test.step();
+        };
+
+        set_key(&mut test, KeyCode::Space, true);
+        assert!(test.world().resource::<Actions>().just_pressed("jump"));
+        assert_eq!(test.events::<ActionEvent>().len(), 1);
+        set_key(&mut test, KeyCode::KeyW, true);
+        set_key(&mut test, KeyCode::Space, false);
+        let actions = test.world().resource::<Actions>();
+        assert!(actions.pressed("jump"));
+        assert!(!actions.just_pressed("jump") && !actions.just_released("jump"));
+        set_key(&mut test, KeyCode::KeyW, false);
+        assert!(test.world().resource::<Actions>().just_released("jump"));
+        assert_eq!(
+            test.events::<ActionEvent>()[0].state,
+            ElementState::Released
+        );
+
+        // Rebinding at runtime takes effect on the next update
+        test.world()
+            .resource_mut::<KeyBindings>()
+            .rebind("jump", [InputBinding::Key(KeyCode::KeyJ)]);
+        set_key(&mut test, KeyCode::Space, true);
+        assert!(!test.world().resource::<Actions>().pressed("jump"));
+        set_key(&mut test, KeyCode::KeyJ, true);
+        assert!(test.world().resource::<Actions>().pressed("jump"));
+    }
+
+    #[test]
+    fn exit_can_be_requested_from_another_thread() {
+        let mut test = TestApp::new(WindowPlugin::default());
+        test.step();
+        assert!(test.events::<AppExit>().is_empty());
+
+        let exit_handle = test.world().resource::<ExitHandle>().clone();
+        std::thread::spawn(move || exit_handle.request_exit())
+            .join()
+            .unwrap();
+        test.step();
+        assert_eq!(test.events::<AppExit>().len(), 1);
+
+        test.step();
+        assert!(test.events::<AppExit>().is_empty());
+    }
+
+    #[test]
+    fn window_names_keep_the_first_window() {
+        let mut test = TestApp::windowless();
+        let first = test.spawn_window(Window::default());
+        test.world().despawn(first);
+        test.step();
+
+        // Reuses the index of the despawned window, so it sorts after the next one
+        let reused = test.spawn_window(Window::default());
+        let other = test.spawn_window(Window::default());
+        assert!(reused > other);
+        for entity in [reused, other] {
+            test.world()
+                .entity_mut(entity)
+                .insert(WindowName("settings".to_string()));
+            test.step();
+        }
+        let names = test.world().resource::<WindowNames>();
+        assert_eq!(names.find_window_by_name("settings"), Some(reused));
+
+        test.world().entity_mut(reused).remove::<WindowName>();
+        test.step();
+        let names = test.world().resource::<WindowNames>();
+        assert_eq!(names.find_window_by_name("settings"), Some(other));
+    }
+
+    #[test]
+    fn window_config_resizes_primary_window() {
+        let mut test = TestApp::new(Default::default());
+        test.step();
+        let entity = test.world().resource::<Windows>().iter().next().unwrap();
+
+        let config = WindowConfig {
+            title: "Settings".to_string(),
+            width: 640,
+            height: 480,
+            ..Default::default()
+        };
+        test.world().insert_resource(config);
+        test.step();
+        let window = test.world().get::<Window>(entity).unwrap();
+        assert_eq!(window.title, "Settings");
+        assert_eq!(
+            (window.resolution.width, window.resolution.height),
+            (640, 480)
+        );
+        let size = window.resolution.physical_size();
+        assert_eq!(test.backend().resize_requests, [(entity, size)]);
+
+        // Applied sizes are cached, the next frame doesn't resize again
+        test.world().get_mut::<Window>(entity).unwrap().title = "Other".to_string();
+        test.step();
+        assert_eq!(test.backend().resize_requests.len(), 1);
+    }
+}
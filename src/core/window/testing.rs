@@ -0,0 +1,97 @@
+//! Runs the window module against a `MockBackend`, for tests that don't need an event loop
+
+use crate::core::window::backend::MockBackend;
+use crate::core::window::components::Window;
+use crate::core::window::resources::{ExitHandle, LoopControl, Monitor, Monitors};
+use crate::core::window::{FrameStepper, WindowPlugin};
+use bevy_app::App;
+use bevy_ecs::prelude::*;
+use bevy_math::{IVec2, UVec2};
+
+/// An app with the window plugin, stepped one frame at a time
+pub(crate) struct TestApp {
+    pub app: App,
+    pub stepper: FrameStepper<MockBackend>,
+}
+
+#[allow(dead_code)]
+impl TestApp {
+    /// Builds everything of the plugin except the event loop and the runner
+    pub fn new(plugin: WindowPlugin) -> Self {
+        let mut app = App::new();
+        plugin.build_with_backend::<MockBackend>(&mut app);
+        app.insert_resource(LoopControl::default());
+        app.insert_resource(ExitHandle::default());
+        app.insert_non_send_resource(MockBackend::default());
+        let stepper = FrameStepper::new(&mut app);
+        TestApp { app, stepper }
+    }
+
+    /// An app without a primary window
+    pub fn windowless() -> Self {
+        TestApp::new(WindowPlugin {
+            primary_window: None,
+            ..Default::default()
+        })
+    }
+
+    /// Runs one frame like the runner does, see `FrameStepper::step`
+    pub fn step(&mut self) {
+        self.stepper.step(&mut self.app, &());
+    }
+
+    /// Spawns a window and runs the frame that creates it
+    pub fn spawn_window(&mut self, window: Window) -> Entity {
+        let entity = self.app.world.spawn(window).id();
+        self.step();
+        entity
+    }
+
+    pub fn world(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+
+    pub fn backend(&self) -> &MockBackend {
+        self.app.world.non_send_resource::<MockBackend>()
+    }
+
+    pub fn backend_mut(&mut self) -> Mut<'_, MockBackend> {
+        self.app.world.non_send_resource_mut::<MockBackend>()
+    }
+
+    pub fn send<E: Event>(&mut self, event: E) {
+        self.app.world.send_event(event);
+    }
+
+    /// The events sent during the last frame
+    pub fn events<E: Event>(&self) -> Vec<&E> {
+        self.app
+            .world
+            .resource::<Events<E>>()
+            .iter_current_update_events()
+            .collect()
+    }
+}
+
+/// A monitor at `x` on the desktop, next to the others
+pub(crate) fn monitor(x: i32, size: UVec2, scale_factor: f64) -> Monitor {
+    Monitor {
+        name: None,
+        position: IVec2::new(x, 0),
+        size,
+        scale_factor,
+        refresh_rate_millihertz: Some(60_000),
+        video_modes: Vec::new(),
+    }
+}
+
+/// A 1080p primary monitor with a 4K monitor at twice the scale on its right
+pub(crate) fn two_monitors() -> Monitors {
+    Monitors {
+        monitors: vec![
+            monitor(0, UVec2::new(1920, 1080), 1.0),
+            monitor(1920, UVec2::new(3840, 2160), 2.0),
+        ],
+        primary: Some(0),
+    }
+}